@@ -55,12 +55,18 @@ app.post("/solve", async (c) => {
   const body = (await c.req.json()) as { pts: { x: number; y: number }[] };
   const points = parseToPoints(body);
   const start = performance.now();
-  await writeFileAndRunSolver(points);
+  let res: string;
+  try {
+    res = await writeFileAndRunSolver(points);
+  } catch (err) {
+    // Malformed or unsupported input is the caller's problem, anything else is ours
+    if (err instanceof SolverError && (err.code === 4 || err.code === 5)) {
+      return c.json({ error: err.message }, 400);
+    }
+    throw err;
+  }
   const end = performance.now() - start;
 
-  const outPath = join(OUTPUT_DIR, "OUT.tsp");
-  const res = await Deno.readTextFile(outPath);
-
   return c.json({ pts: parseFileToPoints(res), time: end });
 });
 
@@ -173,13 +179,17 @@ EOF`;
   await Deno.writeTextFile(path, str);
 }
 
-async function writeFileAndRunSolver(input: Point[]): Promise<void> {
-  const inPath = join(INPUT_DIR, "IN.tsp");
-  await Deno.mkdir(INPUT_DIR, { recursive: true });
-  await Deno.mkdir(OUTPUT_DIR, { recursive: true });
-
-  await writeFile(input, inPath);
+// A non-zero exit from the solver, with its exit code (see `tsp_rust help`) and the
+// "error: ..." line it printed
+class SolverError extends Error {
+  code: number;
+  constructor(code: number, message: string) {
+    super(message);
+    this.code = code;
+  }
+}
 
+async function writeFileAndRunSolver(input: Point[]): Promise<string> {
   const isWindows = Deno.build.os === "windows";
   const executableName = isWindows ? "tsp_rust.exe" : "tsp_rust";
 
@@ -199,16 +209,31 @@ async function writeFileAndRunSolver(input: Point[]): Promise<void> {
     
   }
 
+  // The points go to the solver on stdin, one "x y" pair per line, and the solved
+  // TSP file comes back on stdout so concurrent solves never share a file
   const command = new Deno.Command(executablePath, {
-    args: [inPath, "--no-post"],
+    args: ["-", "--no-post", "-o", "-"],
     cwd: APP_ROOT,
+    stdin: "piped",
+    stdout: "piped",
+    stderr: "piped",
   });
 
-  const { code, stderr } = await command.output();
+  const child = command.spawn();
+  const writer = child.stdin.getWriter();
+  await writer.write(
+    new TextEncoder().encode(input.map((p) => `${p.x} ${p.y}`).join("\n")),
+  );
+  await writer.close();
+
+  const { code, stdout, stderr } = await child.output();
   if (code !== 0) {
-    console.error("Solver error:", new TextDecoder().decode(stderr));
-    throw new Error("Solver failed");
+    const stderrText = new TextDecoder().decode(stderr);
+    console.error("Solver error:", stderrText);
+    const errorLine = stderrText.split("\n").find((l) => l.startsWith("error: "));
+    throw new SolverError(code, errorLine ? errorLine.slice("error: ".length) : "Solver failed");
   }
+  return new TextDecoder().decode(stdout);
 }
 
 // --- Serve ---
@@ -0,0 +1,111 @@
+// Builds, queries and empties a SpatialGrid over the bundled instances, run with
+//   cargo bench --bench spatial_grid [-- FILE...]
+// Without arguments it uses the large files in data/
+
+use std::time::{Duration, Instant};
+
+use tsp_rust::precompute::SpatialGrid;
+use tsp_rust::reader;
+
+const DEFAULT_FILES: [&str; 4] = ["fnl4461", "brd14051", "pla33810", "pla85900"];
+
+fn main() {
+    let args: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+    let files: Vec<String> = if args.is_empty() {
+        DEFAULT_FILES
+            .iter()
+            .map(|name| format!("{}/data/{}.tsp", env!("CARGO_MANIFEST_DIR"), name))
+            .collect()
+    } else {
+        args
+    };
+
+    println!(
+        "{:<12} {:>8} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "instance", "cities", "build", "edge query", "candidates", "remove all", "peak RSS"
+    );
+    for file in &files {
+        let instance = match reader::read_source(file) {
+            Ok(instance) => instance,
+            Err(err) => {
+                eprintln!("skipping {}: {}", file, err);
+                continue;
+            }
+        };
+        let points = &instance.points;
+        let n = points.len();
+        let cities: Vec<usize> = (0..n).collect();
+
+        let start = Instant::now();
+        let mut grid = SpatialGrid::new(points, &cities);
+        let build = start.elapsed();
+
+        // Edges between cities that follow each other in the file, with the radius the
+        // solver would use for a tour with edges of the mean spacing
+        let (min_x, max_x, min_y, max_y) = points.iter().fold(
+            (
+                f32::INFINITY,
+                f32::NEG_INFINITY,
+                f32::INFINITY,
+                f32::NEG_INFINITY,
+            ),
+            |(a, b, c, d), p| (a.min(p.x), b.max(p.x), c.min(p.y), d.max(p.y)),
+        );
+        let radius = 2.0 * ((max_x - min_x) * (max_y - min_y) / n as f32).sqrt();
+        let start = Instant::now();
+        let mut candidates = 0;
+        for i in 0..n {
+            candidates += grid
+                .query_edge_candidates(points[i], points[(i + 1) % n], radius)
+                .len();
+        }
+        let query = start.elapsed();
+
+        // A fixed stride visits every city once in a scattered order
+        let stride = (0..)
+            .map(|k| n / 2 + 1 + k)
+            .find(|&s| gcd(s, n) == 1)
+            .unwrap();
+        let start = Instant::now();
+        for i in 0..n {
+            let city = i * stride % n;
+            grid.remove_point(city);
+            assert!(!grid.contains_point(city));
+        }
+        let remove = start.elapsed();
+
+        println!(
+            "{:<12} {:>8} {:>12} {:>12} {:>12} {:>12} {:>12}",
+            instance.name,
+            n,
+            format_duration(build),
+            format_duration(query),
+            candidates,
+            format_duration(remove),
+            peak_rss()
+        );
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2?}", duration)
+}
+
+// High water mark of the resident set, only available on Linux
+fn peak_rss() -> String {
+    let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
+        return "-".to_string();
+    };
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .map(|value| value.trim().to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
@@ -0,0 +1,252 @@
+// Candidate neighbour lists, the few nearby cities each local search is allowed to connect a
+// city to instead of trying every city of the tour
+
+use rayon::prelude::*;
+use spade::{DelaunayTriangulation, HasPosition, Point2, Triangulation};
+
+use crate::matrix::DistanceMatrix;
+use crate::metric::Metric;
+use crate::precompute::SpatialGrid;
+use crate::shared::Point;
+
+/// How the neighbours of each city are chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CandidateKind {
+    /// The k closest cities
+    #[default]
+    Nearest,
+    /// The closest cities in each of the four quadrants around the city, so a city at the
+    /// edge of a cluster still gets neighbours on the far side of a gap
+    Quadrant,
+    /// The cities sharing a Delaunay triangulation edge with the city
+    Delaunay,
+}
+
+impl CandidateKind {
+    pub fn from_name(name: &str) -> Option<CandidateKind> {
+        match name {
+            "nearest" => Some(CandidateKind::Nearest),
+            "quadrant" => Some(CandidateKind::Quadrant),
+            "delaunay" => Some(CandidateKind::Delaunay),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CandidateKind::Nearest => "nearest",
+            CandidateKind::Quadrant => "quadrant",
+            CandidateKind::Delaunay => "delaunay",
+        }
+    }
+}
+
+/// Up to k neighbours for every city, closest first under the metric. The lists are stored
+/// one after another, the neighbours of city c are neighbours[start[c]..start[c + 1]]
+#[derive(Debug, Clone, Default)]
+pub struct Candidates {
+    start: Vec<usize>,
+    neighbours: Vec<usize>,
+}
+
+impl Candidates {
+    pub fn build(points: &[Point], metric: Metric, kind: CandidateKind, k: usize) -> Self {
+        match kind {
+            CandidateKind::Nearest => Self::nearest(points, metric, k),
+            CandidateKind::Quadrant => Self::quadrant(points, metric, k),
+            CandidateKind::Delaunay => Self::delaunay(points, metric, k),
+        }
+    }
+
+    /// The k closest cities of every city
+    pub fn nearest(points: &[Point], metric: Metric, k: usize) -> Self {
+        let grid = grid_of(points);
+        let lists = (0..points.len())
+            .into_par_iter()
+            .map(|city| {
+                let mut list = grid.k_nearest(points[city], k + 1);
+                list.retain(|&other| other != city);
+                list.truncate(k);
+                list
+            })
+            .collect();
+        Self::from_lists(points, metric, lists)
+    }
+
+    /// k / 4 of the closest cities in each quadrant around every city, topped up with the
+    /// closest of the rest when a quadrant has fewer. Quadrants are only searched among the
+    /// 4k closest cities, which keeps hull cities with empty quadrants from scanning everything
+    pub fn quadrant(points: &[Point], metric: Metric, k: usize) -> Self {
+        let grid = grid_of(points);
+        let per_quadrant = k.div_ceil(4);
+        let lists = (0..points.len())
+            .into_par_iter()
+            .map(|city| {
+                let p = points[city];
+                let near: Vec<usize> = grid
+                    .k_nearest(p, 4 * k + 1)
+                    .into_iter()
+                    .filter(|&other| other != city)
+                    .collect();
+
+                let mut taken = vec![false; near.len()];
+                let mut counts = [0; 4];
+                let mut list = Vec::with_capacity(k);
+                for (i, &other) in near.iter().enumerate() {
+                    let q = points[other];
+                    let quadrant = (q.x < p.x) as usize * 2 + (q.y < p.y) as usize;
+                    if counts[quadrant] < per_quadrant && list.len() < k {
+                        counts[quadrant] += 1;
+                        taken[i] = true;
+                        list.push(other);
+                    }
+                }
+                for (i, &other) in near.iter().enumerate() {
+                    if list.len() == k {
+                        break;
+                    }
+                    if !taken[i] {
+                        list.push(other);
+                    }
+                }
+                list
+            })
+            .collect();
+        Self::from_lists(points, metric, lists)
+    }
+
+    /// The Delaunay neighbours of every city, the k closest of them where there are more.
+    /// Every edge of an optimal Euclidean tour is very likely a Delaunay edge, and there are
+    /// only about six per city
+    pub fn delaunay(points: &[Point], metric: Metric, k: usize) -> Self {
+        let sites: Vec<Site> = points
+            .iter()
+            .enumerate()
+            .map(|(city, p)| Site {
+                position: Point2::new(p.x as f64, p.y as f64),
+                city,
+            })
+            .collect();
+
+        let mut lists: Vec<Vec<usize>> = vec![Vec::new(); points.len()];
+        // Only fails for coordinates too large to triangulate, every city then falls back to
+        // its nearest neighbours below
+        if let Ok(triangulation) = DelaunayTriangulation::<Site>::bulk_load(sites) {
+            for edge in triangulation.undirected_edges() {
+                let [a, b] = edge.vertices();
+                let (a, b) = (a.data().city, b.data().city);
+                lists[a].push(b);
+                lists[b].push(a);
+            }
+        }
+
+        // Coincident cities share one vertex, and fewer than three cities or all of them on
+        // a line may leave some without edges. Those take their nearest neighbours instead
+        if lists.iter().any(|list| list.is_empty()) && points.len() > 1 {
+            let grid = grid_of(points);
+            for (city, list) in lists.iter_mut().enumerate() {
+                if list.is_empty() {
+                    *list = grid.k_nearest(points[city], k + 1);
+                    list.retain(|&other| other != city);
+                }
+            }
+        }
+
+        let mut candidates = Self::from_lists(points, metric, lists);
+        candidates.truncate(k);
+        candidates
+    }
+
+    /// The k closest cities of every city under an explicit matrix, found by sorting its rows
+    pub fn from_matrix(matrix: &DistanceMatrix, k: usize) -> Self {
+        let lists = (0..matrix.len())
+            .into_par_iter()
+            .map(|city| {
+                let mut list: Vec<usize> = (0..matrix.len()).filter(|&c| c != city).collect();
+                list.sort_by(|&a, &b| {
+                    matrix
+                        .dist(city, a)
+                        .total_cmp(&matrix.dist(city, b))
+                        .then(a.cmp(&b))
+                });
+                list.truncate(k);
+                list
+            })
+            .collect();
+        Self::from_sorted_lists(lists)
+    }
+
+    // Sorts every list closest first and packs them together
+    fn from_lists(points: &[Point], metric: Metric, mut lists: Vec<Vec<usize>>) -> Self {
+        lists.par_iter_mut().enumerate().for_each(|(city, list)| {
+            let p = points[city];
+            list.sort_by(|&a, &b| {
+                metric
+                    .dist(p, points[a])
+                    .total_cmp(&metric.dist(p, points[b]))
+                    .then(a.cmp(&b))
+            });
+        });
+        Self::from_sorted_lists(lists)
+    }
+
+    // Packs lists that are already sorted closest first together
+    fn from_sorted_lists(lists: Vec<Vec<usize>>) -> Self {
+        let mut start = Vec::with_capacity(lists.len() + 1);
+        start.push(0);
+        let mut neighbours = Vec::with_capacity(lists.iter().map(Vec::len).sum());
+        for list in lists {
+            neighbours.extend(list);
+            start.push(neighbours.len());
+        }
+        Candidates { start, neighbours }
+    }
+
+    // Keeps the first k neighbours of every city
+    fn truncate(&mut self, k: usize) {
+        let mut start = Vec::with_capacity(self.start.len());
+        start.push(0);
+        let mut neighbours = Vec::with_capacity(self.neighbours.len());
+        for city in 0..self.len() {
+            let list = self.of(city);
+            neighbours.extend_from_slice(&list[..list.len().min(k)]);
+            start.push(neighbours.len());
+        }
+        self.start = start;
+        self.neighbours = neighbours;
+    }
+
+    /// The neighbours of city, closest first
+    #[inline(always)]
+    pub fn of(&self, city: usize) -> &[usize] {
+        &self.neighbours[self.start[city]..self.start[city + 1]]
+    }
+
+    /// Number of cities
+    pub fn len(&self) -> usize {
+        self.start.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn grid_of(points: &[Point]) -> SpatialGrid<'_> {
+    let cities: Vec<usize> = (0..points.len()).collect();
+    SpatialGrid::new(points, &cities)
+}
+
+// A city as a triangulation vertex, carrying its index through the reordering bulk loading does
+struct Site {
+    position: Point2<f64>,
+    city: usize,
+}
+
+impl HasPosition for Site {
+    type Scalar = f64;
+
+    fn position(&self) -> Point2<f64> {
+        self.position
+    }
+}
@@ -0,0 +1,511 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tsp_rust::SolverConfig;
+use tsp_rust::candidates::CandidateKind;
+use tsp_rust::constructor::ConstructorKind;
+use tsp_rust::criterion::CriterionKind;
+use tsp_rust::reader::OutputFormat;
+
+pub const HELP: &str = "tsp_rust - convex hull / LDA insertion TSP solver
+
+USAGE:
+    tsp_rust [solve] <INPUT> [OPTIONS]
+    tsp_rust validate <INPUT> <TOUR>
+    tsp_rust stats <INPUT>
+    tsp_rust convert <INPUT> [--tour <TOUR>] [--format tsp|tour] [-o <PATH>]
+    tsp_rust bench <INPUT>... [--runs <N>] [OPTIONS]
+    tsp_rust help [COMMAND]
+
+INPUT is one of
+    <PATH>                   a TSPLIB file (NODE_COORD_SECTION or EDGE_WEIGHT_SECTION)
+    -                        a TSPLIB file, point array or point list on stdin
+    [x1, y1, x2, y2, ...]    the points written inline
+
+COMMANDS:
+    solve       Solve INPUT and write the tour (the default when no command is given)
+    validate    Check that TOUR visits every city of INPUT exactly once and print its length
+    stats       Print the size, distance function and shape of INPUT
+    convert     Write INPUT as a TSPLIB file, reordered by TOUR when one is given
+    bench       Solve every INPUT and print timings and tour lengths
+
+SOLVE OPTIONS (also accepted by bench):
+    -o, --output <PATH>      Where to write the result, - for stdout (default: -)
+        --format <FORMAT>    tsp writes the cities in tour order, tour writes a TSPLIB
+                             TOUR_SECTION (default: tsp, or tour for EXPLICIT instances)
+        --seed <N>           Seed for every random choice (default: 0)
+        --threads <N>        Number of worker threads (default: one per core)
+        --no-log             Disable the progress bar and all logging
+        --no-post            Disable all post-processing
+        --no-edge-switch     Disable crossing elimination (2-opt on EXPLICIT instances)
+        --two-opt            Replace crossing elimination with a full 2-opt over the
+                             neighbour lists
+        --candidates <KIND>  How neighbour lists are chosen: nearest, quadrant or delaunay
+                             (default: nearest)
+        --neighbours <N>     Length of every neighbour list (default: 8)
+        --constructor <KIND> How the first tour is built: insertion (the convex hull grown
+                             by insertion), nearest, greedy, hilbert or christofides
+                             (default: insertion)
+        --criterion <KIND>   How construction and relp score an insertion: lda, cheapest,
+                             farthest or angle-cheapest (default: lda)
+        --starts <N>         Build and post-process N tours in parallel and keep the
+                             shortest, every one after the first with a noisy constructor
+                             and insertion scores, then print the spread of their lengths
+                             (default: 1)
+        --noise <X>          Noise of those starts as a fraction of the median score of
+                             the convex hull edges, of the edge lengths for greedy and
+                             christofides and of the city spacing for hilbert, nearest
+                             starts from a random city instead (default: 0.3)
+        --batch <N>          Insert up to N points at once on hull edges that do not affect
+                             each other, scoring them in parallel, for longer tours
+                             (default: 1)
+        --no-or-opt          Disable or-opt segment moves over the neighbour lists
+        --three-opt          Run a sequential 3-opt over the neighbour lists after or-opt
+        --no-relp            Disable reinserting the reluctant points
+        --relp-first         Reinsert the reluctant points right after construction, before
+                             the local searches, instead of after 3-opt
+        --lk                 Finish with a Lin-Kernighan search over the neighbour lists
+        --lk-time <SECONDS>  Stop the Lin-Kernighan search after this long (default: run
+                             until no chain of moves improves the tour)
+        --time-limit <SECONDS>
+                             Spend what is left of this many seconds after the phases above
+                             kicking the tour with double bridges and repairing it with the
+                             strongest local search asked for (2-opt, --three-opt or --lk)
+    -h, --help               Print this help
+
+BENCH OPTIONS:
+        --runs <N>           Solve every input N times and report the fastest (default: 1)
+
+EXIT CODES:
+    0    Success
+    2    Bad command line, or a flag that does not apply to an EXPLICIT instance
+    3    An input could not be read or the output could not be written
+    4    Malformed input, the message names the line, section and token
+    5    Input uses an EDGE_WEIGHT_TYPE or EDGE_WEIGHT_FORMAT the solver does not support
+    6    The tour given to validate or convert is not a permutation of the cities
+";
+
+// The commands help can describe on their own
+const COMMANDS: [&str; 5] = ["solve", "validate", "stats", "convert", "bench"];
+
+/// The help for one command, cut from the sections of HELP that apply to it: its usage line,
+/// the INPUT forms, the options it takes and the exit codes. None gives the whole of HELP
+pub fn help(command: Option<&str>) -> String {
+    let Some(command) = command else {
+        return HELP.to_string();
+    };
+    let sections: Vec<&str> = HELP.trim_end().split("\n\n").collect();
+    let section = |heading: &str| {
+        sections
+            .iter()
+            .find(|section| section.starts_with(heading))
+            .copied()
+            .unwrap_or_default()
+    };
+    let line = |heading: &str, prefix: &str| {
+        section(heading)
+            .lines()
+            .find(|line| line.trim_start().starts_with(prefix))
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+
+    let usage = if command == "solve" {
+        line("USAGE:", "tsp_rust [solve]")
+    } else {
+        line("USAGE:", &format!("tsp_rust {} ", command))
+    };
+    let description = line("COMMANDS:", &format!("{} ", command));
+    let description = description[command.len()..].trim_start();
+    let mut text = format!(
+        "tsp_rust {} - {}\n\nUSAGE:\n    {}\n\n{}\n",
+        command,
+        description,
+        usage,
+        section("INPUT")
+    );
+    if command == "solve" || command == "bench" {
+        text += &format!("\n{}\n", section("SOLVE OPTIONS"));
+    }
+    if command == "bench" {
+        text += &format!("\n{}\n", section("BENCH OPTIONS"));
+    }
+    text += &format!("\n{}\n", section("EXIT CODES"));
+    text
+}
+
+#[derive(Debug)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub struct SolveArgs {
+    pub input: String,
+    pub output: String,
+    pub format: Option<OutputFormat>,
+    pub threads: Option<usize>,
+    pub config: SolverConfig,
+}
+
+pub struct ConvertArgs {
+    pub input: String,
+    pub tour: Option<String>,
+    pub output: String,
+    pub format: Option<OutputFormat>,
+}
+
+pub struct BenchArgs {
+    pub inputs: Vec<String>,
+    pub runs: usize,
+    pub threads: Option<usize>,
+    pub config: SolverConfig,
+}
+
+pub enum Command {
+    Solve(SolveArgs),
+    Validate {
+        input: String,
+        tour: String,
+    },
+    Stats {
+        input: String,
+    },
+    Convert(ConvertArgs),
+    Bench(BenchArgs),
+    /// The help for one command, or for all of them
+    Help(Option<&'static str>),
+}
+
+// Hands out arguments one at a time, gluing an inline [x1, y1, ...] array the shell split on
+// spaces back into a single positional
+struct Args<'a> {
+    args: &'a [String],
+    pos: usize,
+}
+
+impl<'a> Args<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let arg = self.args.get(self.pos)?;
+        self.pos += 1;
+        Some(arg)
+    }
+
+    fn value(&mut self, option: &str) -> Result<&'a str, CliError> {
+        self.next()
+            .ok_or_else(|| CliError(format!("{} needs a value", option)))
+    }
+
+    fn number<T: FromStr>(&mut self, option: &str) -> Result<T, CliError> {
+        let value = self.value(option)?;
+        value
+            .parse::<T>()
+            .map_err(|_| CliError(format!("{} needs a whole number, got '{}'", option, value)))
+    }
+
+    fn seconds(&mut self, option: &str) -> Result<Duration, CliError> {
+        let value = self.value(option)?;
+        value
+            .parse::<f64>()
+            .ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(|| {
+                CliError(format!(
+                    "{} needs a number of seconds, got '{}'",
+                    option, value
+                ))
+            })
+    }
+
+    fn positional(&mut self, first: &'a str) -> String {
+        let mut positional = first.to_string();
+        if first.starts_with('[') {
+            while !positional.ends_with(']') {
+                match self.next() {
+                    Some(arg) => {
+                        positional.push(' ');
+                        positional.push_str(arg);
+                    }
+                    None => break,
+                }
+            }
+        }
+        positional
+    }
+}
+
+fn is_option(arg: &str) -> bool {
+    arg.starts_with('-') && arg != "-"
+}
+
+fn parse_format(value: &str) -> Result<OutputFormat, CliError> {
+    match value {
+        "tsp" => Ok(OutputFormat::Tsp),
+        "tour" => Ok(OutputFormat::Tour),
+        other => Err(CliError(format!(
+            "unknown format '{}', expected tsp or tour",
+            other
+        ))),
+    }
+}
+
+// Applies a solver option to config, returning false when arg is not one
+fn parse_solver_flag(arg: &str, config: &mut SolverConfig) -> bool {
+    match arg {
+        "--no-log" => config.log = false,
+        "--no-post" => config.post = false,
+        "--no-edge-switch" => config.edge_swap = false,
+        "--no-or-opt" => config.or_opt = false,
+        "--no-relp" => config.relp = false,
+        "--relp-first" => config.relp_first = true,
+        "--two-opt" => config.two_opt = true,
+        "--three-opt" => config.three_opt = true,
+        "--lk" => config.lin_kernighan = true,
+        _ => return false,
+    }
+    true
+}
+
+// Applies a solver option that takes a value, returning false when arg is not one
+fn parse_solver_option(
+    arg: &str,
+    args: &mut Args,
+    config: &mut SolverConfig,
+) -> Result<bool, CliError> {
+    match arg {
+        "--seed" => config.seed = args.number(arg)?,
+        "--candidates" => {
+            let value = args.value(arg)?;
+            config.candidates = CandidateKind::from_name(value).ok_or_else(|| {
+                CliError(format!(
+                    "unknown candidates '{}', expected nearest, quadrant or delaunay",
+                    value
+                ))
+            })?;
+        }
+        "--constructor" => {
+            let value = args.value(arg)?;
+            config.constructor = ConstructorKind::from_name(value).ok_or_else(|| {
+                CliError(format!(
+                    "unknown constructor '{}', expected insertion, nearest, greedy, hilbert or christofides",
+                    value
+                ))
+            })?;
+        }
+        "--criterion" => {
+            let value = args.value(arg)?;
+            config.criterion = CriterionKind::from_name(value).ok_or_else(|| {
+                CliError(format!(
+                    "unknown criterion '{}', expected lda, cheapest, farthest or angle-cheapest",
+                    value
+                ))
+            })?;
+        }
+        "--lk-time" => config.lk_time = Some(args.seconds(arg)?),
+        "--time-limit" => config.time_limit = Some(args.seconds(arg)?),
+        "--neighbours" => {
+            config.neighbours = args.number(arg)?;
+            if config.neighbours == 0 {
+                return Err(CliError("--neighbours must be at least 1".to_string()));
+            }
+        }
+        "--starts" => {
+            config.starts = args.number(arg)?;
+            if config.starts == 0 {
+                return Err(CliError("--starts must be at least 1".to_string()));
+            }
+        }
+        "--noise" => {
+            let value = args.value(arg)?;
+            config.noise = value
+                .parse::<f32>()
+                .ok()
+                .filter(|noise| noise.is_finite() && *noise >= 0.0)
+                .ok_or_else(|| {
+                    CliError(format!(
+                        "--noise needs a number of at least 0, got '{}'",
+                        value
+                    ))
+                })?;
+        }
+        "--batch" => {
+            config.insert_batch = args.number(arg)?;
+            if config.insert_batch == 0 {
+                return Err(CliError("--batch must be at least 1".to_string()));
+            }
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn unknown(arg: &str, command: &str) -> CliError {
+    CliError(format!(
+        "unknown option '{}' for {}, see tsp_rust help",
+        arg, command
+    ))
+}
+
+pub fn parse(args: &[String]) -> Result<Command, CliError> {
+    let Some(first) = args.first() else {
+        return Err(CliError("no input given, see tsp_rust help".to_string()));
+    };
+    let rest = &args[1..];
+    match first.as_str() {
+        "help" | "-h" | "--help" | "-help" => parse_help(rest),
+        "solve" => parse_solve(rest),
+        "validate" => parse_validate(rest),
+        "stats" => parse_stats(rest),
+        "convert" => parse_convert(rest),
+        "bench" => parse_bench(rest),
+        // A bare input solves it, which is how the backend calls the solver
+        _ => parse_solve(args),
+    }
+}
+
+fn parse_help(args: &[String]) -> Result<Command, CliError> {
+    match args {
+        [] => Ok(Command::Help(None)),
+        [command] => match COMMANDS.iter().find(|&&known| known == command) {
+            Some(&command) => Ok(Command::Help(Some(command))),
+            None if command == "help" => Ok(Command::Help(None)),
+            None => Err(CliError(format!(
+                "unknown command '{}', see tsp_rust help",
+                command
+            ))),
+        },
+        [_, extra, ..] => Err(CliError(format!("unexpected argument '{}'", extra))),
+    }
+}
+
+fn parse_solve(args: &[String]) -> Result<Command, CliError> {
+    let mut args = Args { args, pos: 0 };
+    let mut input = None;
+    let mut output = "-".to_string();
+    let mut format = None;
+    let mut threads = None;
+    let mut config = SolverConfig {
+        log: true,
+        ..SolverConfig::default()
+    };
+
+    while let Some(arg) = args.next() {
+        if parse_solver_flag(arg, &mut config) || parse_solver_option(arg, &mut args, &mut config)?
+        {
+            continue;
+        }
+        match arg {
+            "-h" | "--help" => return Ok(Command::Help(Some("solve"))),
+            "-o" | "--output" => output = args.value(arg)?.to_string(),
+            "--format" => format = Some(parse_format(args.value(arg)?)?),
+            "--threads" => threads = Some(args.number(arg)?),
+            _ if is_option(arg) => return Err(unknown(arg, "solve")),
+            _ if input.is_none() => input = Some(args.positional(arg)),
+            _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+        }
+    }
+
+    Ok(Command::Solve(SolveArgs {
+        input: input.ok_or_else(|| CliError("solve needs an input".to_string()))?,
+        output,
+        format,
+        threads,
+        config,
+    }))
+}
+
+fn parse_validate(args: &[String]) -> Result<Command, CliError> {
+    let mut args = Args { args, pos: 0 };
+    let mut positionals = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg {
+            "-h" | "--help" => return Ok(Command::Help(Some("validate"))),
+            _ if is_option(arg) => return Err(unknown(arg, "validate")),
+            _ => positionals.push(args.positional(arg)),
+        }
+    }
+    let [input, tour]: [String; 2] = positionals
+        .try_into()
+        .map_err(|_| CliError("validate needs an input and a tour".to_string()))?;
+    Ok(Command::Validate { input, tour })
+}
+
+fn parse_stats(args: &[String]) -> Result<Command, CliError> {
+    let mut args = Args { args, pos: 0 };
+    let mut input = None;
+    while let Some(arg) = args.next() {
+        match arg {
+            "-h" | "--help" => return Ok(Command::Help(Some("stats"))),
+            _ if is_option(arg) => return Err(unknown(arg, "stats")),
+            _ if input.is_none() => input = Some(args.positional(arg)),
+            _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+        }
+    }
+    Ok(Command::Stats {
+        input: input.ok_or_else(|| CliError("stats needs an input".to_string()))?,
+    })
+}
+
+fn parse_convert(args: &[String]) -> Result<Command, CliError> {
+    let mut args = Args { args, pos: 0 };
+    let mut input = None;
+    let mut tour = None;
+    let mut output = "-".to_string();
+    let mut format = None;
+    while let Some(arg) = args.next() {
+        match arg {
+            "-h" | "--help" => return Ok(Command::Help(Some("convert"))),
+            "-o" | "--output" => output = args.value(arg)?.to_string(),
+            "--format" => format = Some(parse_format(args.value(arg)?)?),
+            "--tour" => tour = Some(args.value(arg)?.to_string()),
+            _ if is_option(arg) => return Err(unknown(arg, "convert")),
+            _ if input.is_none() => input = Some(args.positional(arg)),
+            _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+        }
+    }
+    Ok(Command::Convert(ConvertArgs {
+        input: input.ok_or_else(|| CliError("convert needs an input".to_string()))?,
+        tour,
+        output,
+        format,
+    }))
+}
+
+fn parse_bench(args: &[String]) -> Result<Command, CliError> {
+    let mut args = Args { args, pos: 0 };
+    let mut inputs = Vec::new();
+    let mut runs = 1;
+    let mut threads = None;
+    // The table is the output, so no progress bars by default
+    let mut config = SolverConfig::default();
+    while let Some(arg) = args.next() {
+        if parse_solver_flag(arg, &mut config) || parse_solver_option(arg, &mut args, &mut config)?
+        {
+            continue;
+        }
+        match arg {
+            "-h" | "--help" => return Ok(Command::Help(Some("bench"))),
+            "--runs" => runs = args.number(arg)?,
+            "--threads" => threads = Some(args.number(arg)?),
+            _ if is_option(arg) => return Err(unknown(arg, "bench")),
+            _ => inputs.push(args.positional(arg)),
+        }
+    }
+    if inputs.is_empty() {
+        return Err(CliError("bench needs at least one input".to_string()));
+    }
+    if runs == 0 {
+        return Err(CliError("--runs must be at least 1".to_string()));
+    }
+    Ok(Command::Bench(BenchArgs {
+        inputs,
+        runs,
+        threads,
+        config,
+    }))
+}
@@ -0,0 +1,455 @@
+// Ways to build the first tour other than growing the convex hull by insertion, for a quick
+// start on huge instances or a baseline to compare the insertion against
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::candidates::Candidates;
+use crate::metric::Metric;
+use crate::precompute::SpatialGrid;
+use crate::shared::{self, Point};
+
+const NONE: usize = usize::MAX;
+
+// Neighbours per city the greedy edge tour picks its edges from
+const GREEDY_NEIGHBOURS: usize = 10;
+// Delaunay neighbours per city the spanning tree is built from, the Euclidean minimum
+// spanning tree only uses Delaunay edges. Other metrics use this many nearest cities instead
+const TREE_NEIGHBOURS: usize = 16;
+// Closest odd cities each odd city may be matched to before falling back to the nearest one
+const MATCH_NEIGHBOURS: usize = 8;
+// Bits per axis of the Hilbert curve
+const HILBERT_ORDER: u32 = 16;
+
+/// Builds a tour through every city of points, as indices in tour order. noise above 0 gives
+/// a different tour for every seed, the way each constructor says, and 0 always the same one
+pub trait Constructor: Sync {
+    fn construct(&self, points: &[Point], metric: Metric, noise: f32, seed: u64) -> Vec<usize>;
+}
+
+/// Goes to the closest city under the metric not visited yet, starting from city 0, or from a
+/// random city with noise
+pub struct NearestNeighbour;
+
+/// Takes the shortest edges between neighbours that leave no city with three edges and close
+/// no cycle, then joins the paths, going from the end of each to the closest free end. Noise
+/// scales every edge length by a random factor within that fraction of 1
+pub struct GreedyEdge;
+
+/// Visits the cities in the order a Hilbert curve over their bounding box passes them.
+/// Sorting is all it costs, which makes it the fastest start and the longest tour. Noise moves
+/// every city by up to that fraction of the mean spacing of the cities before sorting
+pub struct Hilbert;
+
+/// Christofides with a greedy matching: a minimum spanning tree plus a matching of the cities
+/// with an odd number of tree edges, walked as an Euler tour that skips the cities it has
+/// already visited. The greedy matching in place of a minimum weight one loses the 3/2 bound.
+/// Noise scales the edge lengths of both as for the greedy edge tour
+pub struct Christofides;
+
+impl Constructor for NearestNeighbour {
+    fn construct(&self, points: &[Point], metric: Metric, noise: f32, seed: u64) -> Vec<usize> {
+        let n = points.len();
+        if n == 0 {
+            return vec![];
+        }
+        let start = if noise > 0.0 {
+            StdRng::seed_from_u64(seed).gen_range(0..n)
+        } else {
+            0
+        };
+        if !metric.is_planar() {
+            return nearest_neighbour_by_metric(points, metric, start);
+        }
+        // A planar metric grows with the straight-line distance, so the grid finds its closest
+        let all: Vec<usize> = (0..n).collect();
+        let mut grid = SpatialGrid::new(points, &all);
+        let mut tour = Vec::with_capacity(n);
+        let mut current = start;
+        grid.remove_point(current);
+        tour.push(current);
+        while let Some(next) = grid.nearest(points[current]) {
+            grid.remove_point(next);
+            tour.push(next);
+            current = next;
+        }
+        return tour;
+    }
+}
+
+// The nearest neighbour tour when the closest city in a straight line need not be the closest
+// under the metric. Every step compares all the cities left, ties going to the lowest city,
+// which is O(n^2) but GEO and MAN_2D instances are small
+fn nearest_neighbour_by_metric(points: &[Point], metric: Metric, start: usize) -> Vec<usize> {
+    let n = points.len();
+    let mut left: Vec<usize> = (0..n).filter(|&city| city != start).collect();
+    let mut tour = Vec::with_capacity(n);
+    tour.push(start);
+    while !left.is_empty() {
+        let p = points[tour[tour.len() - 1]];
+        let (i, _) = left
+            .iter()
+            .enumerate()
+            .min_by(|&(_, &a), &(_, &b)| {
+                metric
+                    .dist(p, points[a])
+                    .total_cmp(&metric.dist(p, points[b]))
+                    .then(a.cmp(&b))
+            })
+            .unwrap();
+        tour.push(left.swap_remove(i));
+    }
+    return tour;
+}
+
+impl Constructor for GreedyEdge {
+    fn construct(&self, points: &[Point], metric: Metric, noise: f32, seed: u64) -> Vec<usize> {
+        let n = points.len();
+        if n < 3 {
+            return (0..n).collect();
+        }
+        let candidates = Candidates::nearest(points, metric, GREEDY_NEIGHBOURS.min(n - 1));
+        let mut links = vec![[NONE; 2]; n];
+        let mut sets = DisjointSets::new(n);
+        let lengths = EdgeLengths {
+            points,
+            metric,
+            noise,
+            seed,
+        };
+        for (_, a, b) in sorted_edges(&lengths, &candidates) {
+            if links[a][1] == NONE && links[b][1] == NONE && sets.union(a, b) {
+                link(&mut links, a, b);
+            }
+        }
+        return join_paths(points, &links);
+    }
+}
+
+impl Constructor for Hilbert {
+    fn construct(&self, points: &[Point], _metric: Metric, noise: f32, seed: u64) -> Vec<usize> {
+        let min_x = points.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+        let max_x = points.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+        let min_y = points.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+        let max_y = points.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+        let side = (max_x - min_x).max(max_y - min_y);
+        // Square cells keep the curve from stretching along the longer side
+        let scale = if side > 0.0 {
+            ((1u32 << HILBERT_ORDER) - 1) as f32 / side
+        } else {
+            0.0
+        };
+
+        let spacing = side / (points.len() as f32).sqrt();
+        let mut keys: Vec<(u64, usize)> = points
+            .iter()
+            .enumerate()
+            .map(|(city, p)| {
+                let (mut dx, mut dy) = (0.0, 0.0);
+                if noise > 0.0 {
+                    let mut rng = StdRng::seed_from_u64(shared::mix_seed(seed, city as u64));
+                    dx = rng.gen_range(-noise..noise) * spacing;
+                    dy = rng.gen_range(-noise..noise) * spacing;
+                }
+                // Moved off the box the curve is clamped to its edge
+                let x = ((p.x + dx - min_x).clamp(0.0, side) * scale) as u32;
+                let y = ((p.y + dy - min_y).clamp(0.0, side) * scale) as u32;
+                (hilbert_index(x, y), city)
+            })
+            .collect();
+        keys.sort_unstable();
+        return keys.into_iter().map(|(_, city)| city).collect();
+    }
+}
+
+impl Constructor for Christofides {
+    fn construct(&self, points: &[Point], metric: Metric, noise: f32, seed: u64) -> Vec<usize> {
+        let n = points.len();
+        if n < 3 {
+            return (0..n).collect();
+        }
+
+        let lengths = EdgeLengths {
+            points,
+            metric,
+            noise,
+            seed,
+        };
+        // Kruskal over the Delaunay edges, which only hold the shortest ones in the plane
+        let candidates = if metric.is_planar() {
+            Candidates::delaunay(points, metric, TREE_NEIGHBOURS)
+        } else {
+            Candidates::nearest(points, metric, TREE_NEIGHBOURS.min(n - 1))
+        };
+        let mut edges: Vec<(usize, usize)> = Vec::with_capacity(2 * n);
+        let mut sets = DisjointSets::new(n);
+        for (_, a, b) in sorted_edges(&lengths, &candidates) {
+            if sets.union(a, b) {
+                edges.push((a, b));
+            }
+        }
+        // A triangulation that failed leaves pieces, chain them up in city order
+        for city in 1..n {
+            if sets.union(city - 1, city) {
+                edges.push((city - 1, city));
+            }
+        }
+
+        let mut degree = vec![0; n];
+        for &(a, b) in &edges {
+            degree[a] += 1;
+            degree[b] += 1;
+        }
+        let odd: Vec<usize> = (0..n).filter(|&city| degree[city] % 2 == 1).collect();
+        edges.extend(greedy_matching(&lengths, &odd));
+
+        // Euler tour with Hierholzer, a city is written the first time the walk leaves it for
+        // good, which visits every city once and only shortcuts the rest
+        let mut incident: Vec<Vec<usize>> = vec![Vec::new(); n];
+        for (edge, &(a, b)) in edges.iter().enumerate() {
+            incident[a].push(edge);
+            incident[b].push(edge);
+        }
+        let mut used = vec![false; edges.len()];
+        let mut next_edge = vec![0; n];
+        let mut visited = vec![false; n];
+        let mut tour = Vec::with_capacity(n);
+        let mut stack = vec![0];
+        while let Some(&city) = stack.last() {
+            while next_edge[city] < incident[city].len() && used[incident[city][next_edge[city]]] {
+                next_edge[city] += 1;
+            }
+            let Some(&edge) = incident[city].get(next_edge[city]) else {
+                stack.pop();
+                if !visited[city] {
+                    visited[city] = true;
+                    tour.push(city);
+                }
+                continue;
+            };
+            used[edge] = true;
+            let (a, b) = edges[edge];
+            stack.push(if a == city { b } else { a });
+        }
+        return tour;
+    }
+}
+
+// Pairs up cities, shortest pair first among the closest few of each, then whatever is left
+// with the closest city still unmatched
+fn greedy_matching(lengths: &EdgeLengths, cities: &[usize]) -> Vec<(usize, usize)> {
+    let points = lengths.points;
+    let mut grid = SpatialGrid::new(points, cities);
+    let mut pairs: Vec<(f32, usize, usize)> = Vec::new();
+    for &a in cities {
+        for b in grid.k_nearest(points[a], MATCH_NEIGHBOURS + 1) {
+            if a != b {
+                let (a, b) = (a.min(b), a.max(b));
+                pairs.push((lengths.get(a, b), a, b));
+            }
+        }
+    }
+    sort_edges(&mut pairs);
+
+    let mut matching = Vec::with_capacity(cities.len() / 2);
+    for (_, a, b) in pairs {
+        if grid.contains_point(a) && grid.contains_point(b) {
+            grid.remove_point(a);
+            grid.remove_point(b);
+            matching.push((a, b));
+        }
+    }
+    for &a in cities {
+        if !grid.contains_point(a) {
+            continue;
+        }
+        grid.remove_point(a);
+        let Some(b) = grid.nearest(points[a]) else {
+            break;
+        };
+        grid.remove_point(b);
+        matching.push((a, b));
+    }
+    return matching;
+}
+
+// Edge lengths under the metric, scaled by a factor within noise of 1 that only depends on
+// the seed and the two cities, so both copies of an edge get the same length
+struct EdgeLengths<'a> {
+    points: &'a [Point],
+    metric: Metric,
+    noise: f32,
+    seed: u64,
+}
+
+impl EdgeLengths<'_> {
+    // The length of the edge between a and b, a the lower city
+    fn get(&self, a: usize, b: usize) -> f32 {
+        let length = self.metric.dist(self.points[a], self.points[b]);
+        if self.noise == 0.0 {
+            return length;
+        }
+        let mut rng = StdRng::seed_from_u64(shared::mix_seed(
+            shared::mix_seed(self.seed, a as u64),
+            b as u64,
+        ));
+        length * (1.0 + rng.gen_range(-self.noise..self.noise))
+    }
+}
+
+// Every candidate edge once as (length, lower city, higher city), shortest first
+fn sorted_edges(lengths: &EdgeLengths, candidates: &Candidates) -> Vec<(f32, usize, usize)> {
+    let mut edges: Vec<(f32, usize, usize)> = Vec::with_capacity(candidates.len());
+    for a in 0..lengths.points.len() {
+        for &b in candidates.of(a) {
+            let (a, b) = (a.min(b), a.max(b));
+            edges.push((lengths.get(a, b), a, b));
+        }
+    }
+    sort_edges(&mut edges);
+    return edges;
+}
+
+// Shortest first, ties by the cities, without the copies of an edge both ends listed
+fn sort_edges(edges: &mut Vec<(f32, usize, usize)>) {
+    edges.sort_unstable_by(|x, y| x.0.total_cmp(&y.0).then((x.1, x.2).cmp(&(y.1, y.2))));
+    edges.dedup_by(|x, y| (x.1, x.2) == (y.1, y.2));
+}
+
+fn link(links: &mut [[usize; 2]], a: usize, b: usize) {
+    let slot = if links[a][0] == NONE { 0 } else { 1 };
+    links[a][slot] = b;
+    let slot = if links[b][0] == NONE { 0 } else { 1 };
+    links[b][slot] = a;
+}
+
+// Walks the paths links make, every city on one, joining the end of each path to the closest
+// end of a path not walked yet
+fn join_paths(points: &[Point], links: &[[usize; 2]]) -> Vec<usize> {
+    let n = links.len();
+    let ends: Vec<usize> = (0..n).filter(|&city| links[city][1] == NONE).collect();
+    let mut free_ends = SpatialGrid::new(points, &ends);
+    let mut tour = Vec::with_capacity(n);
+
+    let mut start = ends[0];
+    loop {
+        free_ends.remove_point(start);
+        let (mut prev, mut city) = (NONE, start);
+        loop {
+            tour.push(city);
+            let next = links[city]
+                .into_iter()
+                .find(|&other| other != NONE && other != prev);
+            let Some(next) = next else {
+                break;
+            };
+            (prev, city) = (city, next);
+        }
+        free_ends.remove_point(city);
+        let Some(next_start) = free_ends.nearest(points[city]) else {
+            break;
+        };
+        start = next_start;
+    }
+    return tour;
+}
+
+// Position of (x, y) along the Hilbert curve through a square of 2^HILBERT_ORDER cells a side
+fn hilbert_index(mut x: u32, mut y: u32) -> u64 {
+    let side = 1u32 << HILBERT_ORDER;
+    let mut index = 0u64;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Turn the quadrant so the curve inside it runs the standard way
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    return index;
+}
+
+// Union-find over the cities, telling whether an edge would close a cycle
+struct DisjointSets {
+    parent: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(n: usize) -> Self {
+        DisjointSets {
+            parent: (0..n).collect(),
+        }
+    }
+
+    fn find(&mut self, mut city: usize) -> usize {
+        while self.parent[city] != city {
+            self.parent[city] = self.parent[self.parent[city]];
+            city = self.parent[city];
+        }
+        city
+    }
+
+    // Joins the sets of a and b, false when they were one set already
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        self.parent[a] = b;
+        true
+    }
+}
+
+/// How the first tour is built
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConstructorKind {
+    /// Growing the convex hull by insertion under the insertion criterion
+    #[default]
+    Insertion,
+    NearestNeighbour,
+    GreedyEdge,
+    Hilbert,
+    Christofides,
+}
+
+impl ConstructorKind {
+    pub fn from_name(name: &str) -> Option<ConstructorKind> {
+        match name {
+            "insertion" => Some(ConstructorKind::Insertion),
+            "nearest" => Some(ConstructorKind::NearestNeighbour),
+            "greedy" => Some(ConstructorKind::GreedyEdge),
+            "hilbert" => Some(ConstructorKind::Hilbert),
+            "christofides" => Some(ConstructorKind::Christofides),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConstructorKind::Insertion => "insertion",
+            ConstructorKind::NearestNeighbour => "nearest",
+            ConstructorKind::GreedyEdge => "greedy",
+            ConstructorKind::Hilbert => "hilbert",
+            ConstructorKind::Christofides => "christofides",
+        }
+    }
+
+    /// None for the insertion, which the solver runs itself since it also keeps the
+    /// insertion log relp starts from
+    pub fn constructor(&self) -> Option<&'static dyn Constructor> {
+        match self {
+            ConstructorKind::Insertion => None,
+            ConstructorKind::NearestNeighbour => Some(&NearestNeighbour),
+            ConstructorKind::GreedyEdge => Some(&GreedyEdge),
+            ConstructorKind::Hilbert => Some(&Hilbert),
+            ConstructorKind::Christofides => Some(&Christofides),
+        }
+    }
+}
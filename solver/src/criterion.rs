@@ -0,0 +1,187 @@
+// How construction and relp rate putting an inner city into a hull edge. Every criterion
+// scores eight candidates of one edge at a time, the edge takes its best candidate and the
+// edge with the best candidate overall goes first
+
+use rand::rngs::StdRng;
+use std::f32::consts::FRAC_PI_2;
+use std::simd::Select;
+use std::simd::cmp::SimdPartialOrd;
+use std::simd::num::SimdFloat;
+
+use crate::math;
+use crate::metric::Metric;
+use crate::shared::SimdF32;
+
+// Keeps a candidate lying on the edge from dividing by zero
+const EPSILON: f32 = 1e-10;
+
+/// Scores putting each lane of c between a and b, higher is better. Only a positive score is
+/// an insertion, an edge whose candidates all score zero or less is left for the fallback.
+/// rand_dif adds the same uniform noise to every lane, as `math::lda` does
+pub trait InsertionCriterion: Sync {
+    fn score(
+        &self,
+        a_x: SimdF32,
+        a_y: SimdF32,
+        b_x: SimdF32,
+        b_y: SimdF32,
+        c_x: SimdF32,
+        c_y: SimdF32,
+        rng: &mut StdRng,
+        rand_dif: f32,
+        metric: Metric,
+    ) -> SimdF32;
+}
+
+/// The angle at c divided by the distance from c to the edge, see `math::lda`
+pub struct Lda;
+
+/// The reciprocal of the detour d(a, c) + d(c, b) - d(a, b), so the cheapest insertion wins
+pub struct Cheapest;
+
+/// The distance from c to the edge for the cities inside the circle with the edge as its
+/// diameter, so the edges pull in the cities furthest from them first without long detours.
+/// A city outside the circle scores next to nothing, cheapest first, so it only goes in when
+/// no edge has a city inside
+pub struct Farthest;
+
+/// The angle at c divided by the detour, cheap insertions win but a city that would turn the
+/// tour sharply back on itself is held back
+pub struct AngleCheapest;
+
+impl InsertionCriterion for Lda {
+    fn score(
+        &self,
+        a_x: SimdF32,
+        a_y: SimdF32,
+        b_x: SimdF32,
+        b_y: SimdF32,
+        c_x: SimdF32,
+        c_y: SimdF32,
+        rng: &mut StdRng,
+        rand_dif: f32,
+        metric: Metric,
+    ) -> SimdF32 {
+        math::lda(a_x, a_y, b_x, b_y, c_x, c_y, rng, rand_dif, metric)
+    }
+}
+
+impl InsertionCriterion for Cheapest {
+    fn score(
+        &self,
+        a_x: SimdF32,
+        a_y: SimdF32,
+        b_x: SimdF32,
+        b_y: SimdF32,
+        c_x: SimdF32,
+        c_y: SimdF32,
+        rng: &mut StdRng,
+        rand_dif: f32,
+        metric: Metric,
+    ) -> SimdF32 {
+        let (ab, bc, ac) = sides(a_x, a_y, b_x, b_y, c_x, c_y, metric);
+        let detour = (ac + bc - ab).simd_max(SimdF32::splat(EPSILON));
+        SimdF32::splat(1.0) / detour + math::noise(rng, rand_dif)
+    }
+}
+
+impl InsertionCriterion for Farthest {
+    fn score(
+        &self,
+        a_x: SimdF32,
+        a_y: SimdF32,
+        b_x: SimdF32,
+        b_y: SimdF32,
+        c_x: SimdF32,
+        c_y: SimdF32,
+        rng: &mut StdRng,
+        rand_dif: f32,
+        metric: Metric,
+    ) -> SimdF32 {
+        let (ab, bc, ac) = sides(a_x, a_y, b_x, b_y, c_x, c_y, metric);
+        let dist = math::edge_distance(a_x, a_y, b_x, b_y, c_x, c_y, (ab, bc, ac), metric);
+        // Seen from inside the circle the edge spans more than a right angle
+        let inside = math::insertion_angle(ab, bc, ac).simd_gt(SimdF32::splat(FRAC_PI_2));
+        let detour = (ac + bc - ab).simd_max(SimdF32::splat(EPSILON));
+        let outside = SimdF32::splat(EPSILON) / (SimdF32::splat(1.0) + detour);
+        inside.select(dist, outside) + math::noise(rng, rand_dif)
+    }
+}
+
+impl InsertionCriterion for AngleCheapest {
+    fn score(
+        &self,
+        a_x: SimdF32,
+        a_y: SimdF32,
+        b_x: SimdF32,
+        b_y: SimdF32,
+        c_x: SimdF32,
+        c_y: SimdF32,
+        rng: &mut StdRng,
+        rand_dif: f32,
+        metric: Metric,
+    ) -> SimdF32 {
+        let (ab, bc, ac) = sides(a_x, a_y, b_x, b_y, c_x, c_y, metric);
+        let angle = math::insertion_angle(ab, bc, ac);
+        let detour = (ac + bc - ab).simd_max(SimdF32::splat(EPSILON));
+        angle / detour + math::noise(rng, rand_dif)
+    }
+}
+
+// Lengths of (a, b), (b, c) and (a, c)
+#[inline(always)]
+fn sides(
+    a_x: SimdF32,
+    a_y: SimdF32,
+    b_x: SimdF32,
+    b_y: SimdF32,
+    c_x: SimdF32,
+    c_y: SimdF32,
+    metric: Metric,
+) -> (SimdF32, SimdF32, SimdF32) {
+    (
+        metric.dist_simd(a_x, a_y, b_x, b_y),
+        metric.dist_simd(b_x, b_y, c_x, c_y),
+        metric.dist_simd(a_x, a_y, c_x, c_y),
+    )
+}
+
+/// Which insertion criterion construction and relp use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CriterionKind {
+    #[default]
+    Lda,
+    Cheapest,
+    Farthest,
+    AngleCheapest,
+}
+
+impl CriterionKind {
+    pub fn from_name(name: &str) -> Option<CriterionKind> {
+        match name {
+            "lda" => Some(CriterionKind::Lda),
+            "cheapest" => Some(CriterionKind::Cheapest),
+            "farthest" => Some(CriterionKind::Farthest),
+            "angle-cheapest" => Some(CriterionKind::AngleCheapest),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CriterionKind::Lda => "lda",
+            CriterionKind::Cheapest => "cheapest",
+            CriterionKind::Farthest => "farthest",
+            CriterionKind::AngleCheapest => "angle-cheapest",
+        }
+    }
+
+    pub fn criterion(&self) -> &'static dyn InsertionCriterion {
+        match self {
+            CriterionKind::Lda => &Lda,
+            CriterionKind::Cheapest => &Cheapest,
+            CriterionKind::Farthest => &Farthest,
+            CriterionKind::AngleCheapest => &AngleCheapest,
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use std::simd::Simd;
 use std::simd::cmp::SimdPartialOrd;
 
-use crate::math;
+use crate::metric::Metric;
 use crate::shared;
+use crate::tour::ArrayTour;
 type SimdBool = std::simd::Mask<i32, 8>;
 type SimdF32 = Simd<f32, 8>;
 #[inline(always)]
@@ -50,23 +51,24 @@ fn segments_intersect_simd(
     return straddle1 & straddle2;
 }
 
-pub fn eliminate_crossings(tour: &mut Vec<shared::Point>) -> bool {
+pub fn eliminate_crossings(tour: &mut ArrayTour, points: &[shared::Point], metric: Metric) -> bool {
     let n = tour.len();
     if n < 4 {
         return false;
     }
-
-    let mut improved = false;
+    let order = tour.order();
 
     // Check all pairs of edges for crossings
     for i in 0..n {
         let next_i = (i + 1) % n;
 
         // Get coordinates for edge i
-        let edge1_ax = tour[i].x;
-        let edge1_ay = tour[i].y;
-        let edge1_bx = tour[next_i].x;
-        let edge1_by = tour[next_i].y;
+        let edge1_a = points[order[i]];
+        let edge1_b = points[order[next_i]];
+        let edge1_ax = edge1_a.x;
+        let edge1_ay = edge1_a.y;
+        let edge1_bx = edge1_b.x;
+        let edge1_by = edge1_b.y;
 
         // Process edges in chunks of 8 using SIMD
         for chunk_start in ((i + 2)..n).step_by(8) {
@@ -85,11 +87,12 @@ pub fn eliminate_crossings(tour: &mut Vec<shared::Point>) -> bool {
                     continue;
                 }
 
-                let next_j = (j + 1) % n;
-                ax_arr[count] = tour[j].x;
-                ay_arr[count] = tour[j].y;
-                bx_arr[count] = tour[next_j].x;
-                by_arr[count] = tour[next_j].y;
+                let a = points[order[j]];
+                let b = points[order[(j + 1) % n]];
+                ax_arr[count] = a.x;
+                ay_arr[count] = a.y;
+                bx_arr[count] = b.x;
+                by_arr[count] = b.y;
                 valid_mask[count] = true;
                 edge_indices[count] = j;
                 count += 1;
@@ -128,24 +131,17 @@ pub fn eliminate_crossings(tour: &mut Vec<shared::Point>) -> bool {
                     let j = edge_indices[lane];
 
                     // Calculate improvement from uncrossing
-                    let current_dist = math::calc_dist(tour[i], tour[next_i])
-                        + math::calc_dist(tour[j], tour[(j + 1) % n]);
+                    let (a, b) = (points[order[i]], points[order[next_i]]);
+                    let (c, d) = (points[order[j]], points[order[(j + 1) % n]]);
+                    let current_dist = metric.dist(a, b) + metric.dist(c, d);
 
-                    let uncrossed_dist = math::calc_dist(tour[i], tour[j])
-                        + math::calc_dist(tour[next_i], tour[(j + 1) % n]);
+                    let uncrossed_dist = metric.dist(a, c) + metric.dist(b, d);
 
                     if uncrossed_dist < current_dist {
-                        // Perform 2-opt swap to uncross
-                        let mut start = next_i;
-                        let mut end = j;
-
-                        if start > end {
-                            std::mem::swap(&mut start, &mut end);
-                        }
-
-                        // Reverse the segment between the crossing edges
-                        tour[start..=end].reverse();
-                        improved = true;
+                        // Perform 2-opt swap to uncross, a b ... c d becomes a c ... b d
+                        let (a, b) = (order[i], order[next_i]);
+                        let (c, d) = (order[j], order[(j + 1) % n]);
+                        tour.two_opt_move(a, b, d, c);
 
                         // Early termination after first improvement to avoid conflicts
                         return true;
@@ -155,14 +151,18 @@ pub fn eliminate_crossings(tour: &mut Vec<shared::Point>) -> bool {
         }
     }
 
-    return improved;
+    return false;
 }
 
 // Helper function to repeatedly eliminate crossings until no more are found
-pub fn eliminate_all_crossings(tour: &mut Vec<shared::Point>) -> bool {
+pub fn eliminate_all_crossings(
+    tour: &mut ArrayTour,
+    points: &[shared::Point],
+    metric: Metric,
+) -> bool {
     let mut total_improved = false;
 
-    while eliminate_crossings(tour) {
+    while eliminate_crossings(tour, points, metric) {
         total_improved = true;
     }
 
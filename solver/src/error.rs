@@ -0,0 +1,162 @@
+use std::fmt;
+use std::io;
+
+// Process exit codes, one per class of failure so callers can tell them apart
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_IO: i32 = 3;
+pub const EXIT_MALFORMED: i32 = 4;
+pub const EXIT_UNSUPPORTED: i32 = 5;
+pub const EXIT_INVALID_TOUR: i32 = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    ExpectedNumber,
+    ExpectedNodeId,
+    /// A node number that an earlier line already gave to another city
+    RepeatedNodeId {
+        first_line: usize,
+    },
+    /// A data line with fewer columns than the section needs
+    MissingColumns {
+        expected: usize,
+    },
+    /// A header value this solver cannot handle, named by the header key
+    Unsupported {
+        key: &'static str,
+    },
+    /// A NODE_COORD_SECTION with another number of cities than the DIMENSION header
+    DimensionMismatch {
+        dimension: usize,
+        found: usize,
+    },
+    MissingWeightFormat,
+    /// A FULL_MATRIX weight that differs from its mirror image, between the nodes of the row
+    /// and column it sits in
+    AsymmetricWeight {
+        row: usize,
+        col: usize,
+    },
+    WrongValueCount {
+        expected: usize,
+        found: usize,
+    },
+    OddPointCount {
+        found: usize,
+    },
+    NoCities,
+}
+
+/// Why an input could not be read, with the line (1 based) and section it happened in
+/// and the token that caused it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// 0 when the problem is with the input as a whole rather than one line
+    pub line: usize,
+    pub section: &'static str,
+    pub token: String,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub fn new(line: usize, section: &'static str, token: &str, kind: ParseErrorKind) -> Self {
+        ParseError {
+            line,
+            section,
+            token: token.to_string(),
+            kind,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self.kind {
+            ParseErrorKind::Unsupported { .. } => EXIT_UNSUPPORTED,
+            _ => EXIT_MALFORMED,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "line {} ({}): ", self.line, self.section)?;
+        } else {
+            write!(f, "{}: ", self.section)?;
+        }
+        match &self.kind {
+            ParseErrorKind::ExpectedNumber => write!(f, "expected a number, got '{}'", self.token),
+            ParseErrorKind::ExpectedNodeId => {
+                write!(f, "expected a node number, got '{}'", self.token)
+            }
+            ParseErrorKind::RepeatedNodeId { first_line } => write!(
+                f,
+                "node {} was already given on line {}",
+                self.token, first_line
+            ),
+            ParseErrorKind::MissingColumns { expected } => {
+                write!(f, "expected {} columns, got '{}'", expected, self.token)
+            }
+            ParseErrorKind::Unsupported { key } => {
+                write!(f, "unsupported {} '{}'", key, self.token)
+            }
+            ParseErrorKind::DimensionMismatch { dimension, found } => write!(
+                f,
+                "DIMENSION is {} but NODE_COORD_SECTION has {} cities",
+                dimension, found
+            ),
+            ParseErrorKind::MissingWeightFormat => write!(
+                f,
+                "EDGE_WEIGHT_TYPE is EXPLICIT but no EDGE_WEIGHT_FORMAT was given"
+            ),
+            ParseErrorKind::AsymmetricWeight { row, col } => write!(
+                f,
+                "weight {} from node {} to {} differs from the weight back, only symmetric \
+                 matrices are supported",
+                self.token, row, col
+            ),
+            ParseErrorKind::WrongValueCount { expected, found } => write!(
+                f,
+                "expected {} values for {}, found {}",
+                expected, self.token, found
+            ),
+            ParseErrorKind::OddPointCount { found } => {
+                write!(f, "got {} numbers, every point needs an x and a y", found)
+            }
+            ParseErrorKind::NoCities => write!(f, "no cities found"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Failure to get a problem or tour from a file, stdin or the command line
+#[derive(Debug)]
+pub enum ReadError {
+    Io { source: String, error: io::Error },
+    Parse(ParseError),
+}
+
+impl ReadError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ReadError::Io { .. } => EXIT_IO,
+            ReadError::Parse(err) => err.exit_code(),
+        }
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io { source, error } => write!(f, "could not read {}: {}", source, error),
+            ReadError::Parse(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+impl From<ParseError> for ReadError {
+    fn from(err: ParseError) -> Self {
+        ReadError::Parse(err)
+    }
+}
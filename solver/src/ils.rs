@@ -0,0 +1,97 @@
+// Iterated local search: kick the best tour a little, let a local search repair it and keep
+// the result when it is shorter, until the time runs out
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Instant;
+
+use crate::metric::Metric;
+use crate::shared::Point;
+use crate::tour::ArrayTour;
+
+// Longest of the two paths a kick swaps, short paths keep the damage in one area so the local
+// search only has to repair that area
+const MAX_SEGMENT: usize = 50;
+// A kick this much longer is still taken, the gains are sums of f32 distances in f64 and a
+// kick the search repairs exactly can come out a rounding error either way
+const EPSILON: f64 = 1e-7;
+
+/// Kicks tour with segment-local double-bridge moves until the deadline, each followed by
+/// local_search from the cities whose edges the kick changed. local_search returns how much
+/// shorter it made the tour. A kick is kept when the tour ends up no longer than before, up
+/// to a rounding error, otherwise only the changes since the kick are undone. Those rounding
+/// errors can add up, so the shortest tour seen is kept aside and put back at the end.
+/// Returns the number of kicks tried.
+pub fn iterated_local_search(
+    tour: &mut ArrayTour,
+    points: &[Point],
+    metric: Metric,
+    deadline: Instant,
+    seed: u64,
+    mut local_search: impl FnMut(&mut ArrayTour, &[usize]) -> f64,
+) -> usize {
+    let n = tour.len();
+    if n < 8 {
+        return 0;
+    }
+    let d = |a: usize, b: usize| metric.dist(points[a], points[b]) as f64;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut kicks = 0;
+    // Lengths relative to the starting tour
+    let mut length = 0.0;
+    let mut best_length = 0.0;
+    let mut best_order = tour.order().to_vec();
+
+    while Instant::now() < deadline {
+        tour.checkpoint();
+        let [t1, t2, t3, t4, t5, t6] = double_bridge(tour, &mut rng);
+        // t1 t2, t5 t6 and t3 t4 became t1 t6, t3 t2 and t5 t4
+        let added = d(t1, t6) + d(t3, t2) + d(t5, t4) - d(t1, t2) - d(t5, t6) - d(t3, t4);
+        let gain = local_search(tour, &[t1, t2, t3, t4, t5, t6]);
+        kicks += 1;
+
+        // Taking equal tours too lets the search drift across plateaus
+        let change = added - gain;
+        if change > EPSILON {
+            tour.rollback();
+            continue;
+        }
+        length += change;
+        if length < best_length - EPSILON {
+            best_length = length;
+            best_order.clear();
+            best_order.extend_from_slice(tour.order());
+        }
+    }
+
+    if length > best_length {
+        *tour = ArrayTour::new(best_order);
+    }
+    return kicks;
+}
+
+// Swaps two short neighbouring paths after a random city, t1 [t2 .. t5] [t6 .. t3] t4 becomes
+// t1 [t6 .. t3] [t2 .. t5] t4, and returns the cities at the ends of the changed edges
+fn double_bridge(tour: &mut ArrayTour, rng: &mut StdRng) -> [usize; 6] {
+    let n = tour.len();
+    // Both paths and the two cities around them must fit in the tour
+    let max = MAX_SEGMENT.min((n - 2) / 2);
+    let first_len = rng.gen_range(1..=max);
+    let second_len = rng.gen_range(1..=max);
+
+    let t1 = tour.order()[rng.gen_range(0..n)];
+    let t2 = tour.next(t1);
+    let mut t5 = t2;
+    for _ in 1..first_len {
+        t5 = tour.next(t5);
+    }
+    let t6 = tour.next(t5);
+    let mut t3 = t6;
+    for _ in 1..second_len {
+        t3 = tour.next(t3);
+    }
+    let t4 = tour.next(t3);
+
+    tour.swap_segments(t1, t2, t3, t4, t5, t6);
+    return [t1, t2, t3, t4, t5, t6];
+}
@@ -0,0 +1,638 @@
+// Insertion of the inner points into the hull, driven by a priority queue of the best
+// candidate of every hull edge under the insertion criterion
+
+use indicatif::ProgressBar;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::criterion::InsertionCriterion;
+use crate::metric::Metric;
+use crate::precompute::{EdgeScan, SpatialGrid};
+use crate::relp::InsertPointResult;
+use crate::shared::{self, Point, SimdF32};
+use crate::tour::Tour;
+
+// Fewest edges worth scoring on the thread pool, most insertions only touch a handful
+const PARALLEL_MIN: usize = 32;
+
+// The best candidate of the edge (best_a, next) when it was scored. It is only good while
+// the edge is still in the hull and has not been scored again since
+#[derive(Debug, Clone, Copy)]
+struct EdgeEntry {
+    result: InsertPointResult,
+    next: usize,
+    version: u32,
+}
+
+impl PartialEq for EdgeEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for EdgeEntry {}
+
+impl PartialOrd for EdgeEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for EdgeEntry {
+    // Highest score first, equal scores go to the edge starting at the lowest city
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.result
+            .lda
+            .total_cmp(&other.result.lda)
+            .then(other.result.best_a.cmp(&self.result.best_a))
+            .then(other.result.best_c.cmp(&self.result.best_c))
+    }
+}
+
+// A bound on the search radius of the edge starting at a, see EdgeScan
+#[derive(Debug, Clone, Copy)]
+struct Bound {
+    value: f32,
+    a: usize,
+    version: u32,
+}
+
+impl PartialEq for Bound {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Bound {}
+
+impl PartialOrd for Bound {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Bound {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value
+            .total_cmp(&other.value)
+            .then(self.a.cmp(&other.a))
+    }
+}
+
+// The queued edges, each known by the city it starts at. Every edge keeps the answer it got
+// from the grid for as long as the same search would still give it: while the search radius
+// stays within the bounds of what it looked at, and until one of the cities it looked at goes
+// in. The watchers of a city are the edges that looked at it, so the queue always holds the
+// best candidate every edge would get if it was scored from scratch
+struct EdgeQueue<'a> {
+    points: &'a [Point],
+    n: usize,
+    metric: Metric,
+    criterion: &'a dyn InsertionCriterion,
+    // Uniform noise added to the scores, see math::noise
+    rand_dif: f32,
+    seed: u64,
+    heap: BinaryHeap<EdgeEntry>,
+    // The edges whose answer changes once the radius shrinks below, grows to or grows past the
+    // bound, largest inside, smallest outside and fewest cells first
+    inside: BinaryHeap<Bound>,
+    outside: BinaryHeap<Reverse<Bound>>,
+    cells: BinaryHeap<Reverse<Bound>>,
+    version: Vec<u32>,
+    watchers: Vec<Vec<(usize, u32)>>,
+}
+
+impl<'a> EdgeQueue<'a> {
+    // Scores the edges starting at every city of starts, in parallel when there are enough
+    fn score(
+        &self,
+        hull: &Tour,
+        spatial_grid: &SpatialGrid,
+        starts: &[usize],
+        search_radius: f32,
+    ) -> Vec<(usize, Scored)> {
+        let score = |&a: &usize| {
+            let scored = score_edge(
+                self.points,
+                spatial_grid,
+                a,
+                hull.next(a),
+                search_radius,
+                self.n,
+                self.metric,
+                self.criterion,
+                self.rand_dif,
+                self.seed,
+            );
+            (a, scored)
+        };
+        if starts.len() < PARALLEL_MIN {
+            starts.iter().map(score).collect()
+        } else {
+            starts.par_iter().map(score).collect()
+        }
+    }
+
+    // Scores the edges starting at every city of starts and queues them
+    fn score_many(
+        &mut self,
+        hull: &Tour,
+        spatial_grid: &SpatialGrid,
+        starts: &[usize],
+        search_radius: f32,
+    ) {
+        for (a, scored) in self.score(hull, spatial_grid, starts, search_radius) {
+            self.push(a, scored);
+        }
+    }
+
+    fn push(&mut self, a: usize, scored: Scored) {
+        self.version[a] += 1;
+        let version = self.version[a];
+        let scan = scored.scan;
+        for &c in &scan.scanned {
+            self.watchers[c].push((a, version));
+        }
+        let bound = |value: f32| Bound { value, a, version };
+        if scan.inside > f32::NEG_INFINITY {
+            self.inside.push(bound(scan.inside));
+        }
+        if scan.outside < f32::INFINITY {
+            self.outside.push(Reverse(bound(scan.outside)));
+        }
+        self.cells.push(Reverse(bound(scan.cells as f32)));
+        if let Some(mut entry) = scored.entry {
+            entry.version = version;
+            self.heap.push(entry);
+        }
+    }
+
+    // The edges whose answer would change with search_radius, leaving their bounds behind
+    fn outgrown(&mut self, spatial_grid: &SpatialGrid, search_radius: f32) -> Vec<usize> {
+        let radius_sq = search_radius * search_radius;
+        let cells = spatial_grid.cells_to_check(search_radius) as f32;
+        let version = &self.version;
+        let current = |bound: &Bound| bound.version == version[bound.a];
+        let mut outgrown = Vec::new();
+        while let Some(&bound) = self.inside.peek() {
+            if current(&bound) && bound.value <= radius_sq {
+                break;
+            }
+            self.inside.pop();
+            if current(&bound) {
+                outgrown.push(bound.a);
+            }
+        }
+        // Edges that looked further out than needed keep their answer, see EdgeScan
+        let heaps = [
+            (&mut self.outside, radius_sq, false),
+            (&mut self.cells, cells, true),
+        ];
+        for (heap, radius, reaches) in heaps {
+            while let Some(&Reverse(bound)) = heap.peek() {
+                let holds = if reaches {
+                    bound.value >= radius
+                } else {
+                    bound.value > radius
+                };
+                if current(&bound) && holds {
+                    break;
+                }
+                heap.pop();
+                if current(&bound) {
+                    outgrown.push(bound.a);
+                }
+            }
+        }
+        outgrown
+    }
+
+    // The best queued edge that is still current
+    fn pop(&mut self, hull: &Tour) -> Option<EdgeEntry> {
+        while let Some(entry) = self.heap.pop() {
+            let a = entry.result.best_a;
+            if hull.next(a) == entry.next && entry.version == self.version[a] {
+                return Some(entry);
+            }
+        }
+        None
+    }
+
+    // Median score of the best candidates of the hull edges without noise
+    fn median_score(&self, hull: &Tour, spatial_grid: &SpatialGrid, search_radius: f32) -> f32 {
+        let mut scores: Vec<f32> = self
+            .score(hull, spatial_grid, hull.cities(), search_radius)
+            .into_iter()
+            .filter_map(|(_, scored)| scored.entry.map(|entry| entry.result.lda))
+            .collect();
+        if scores.is_empty() {
+            return 0.0;
+        }
+        let middle = scores.len() / 2;
+        let (_, median, _) = scores.select_nth_unstable_by(middle, f32::total_cmp);
+        *median
+    }
+
+    // Up to size of the best queued edges that do not get in each other's way: no two share
+    // a city, and neither looked at the other's candidate. Edges that do are put back for a
+    // later round
+    fn pop_batch(&mut self, hull: &Tour, size: usize) -> Vec<InsertPointResult> {
+        let mut chosen: Vec<EdgeEntry> = Vec::with_capacity(size);
+        let mut deferred = Vec::new();
+        while chosen.len() < size {
+            let Some(entry) = self.pop(hull) else {
+                break;
+            };
+            if chosen.iter().any(|other| self.conflict(&entry, other)) {
+                deferred.push(entry);
+                // The queue top is crowded, better to insert what there is
+                if deferred.len() >= size {
+                    break;
+                }
+                continue;
+            }
+            chosen.push(entry);
+        }
+        self.heap.extend(deferred);
+        chosen.into_iter().map(|entry| entry.result).collect()
+    }
+
+    fn conflict(&self, x: &EdgeEntry, y: &EdgeEntry) -> bool {
+        let cities_x = [x.result.best_a, x.next, x.result.best_c];
+        let cities_y = [y.result.best_a, y.next, y.result.best_c];
+        let watches = |c: usize, a: usize| self.watchers[c].contains(&(a, self.version[a]));
+        cities_x.iter().any(|city| cities_y.contains(city))
+            || watches(x.result.best_c, y.result.best_a)
+            || watches(y.result.best_c, x.result.best_a)
+    }
+
+    // The edges that looked at c, which have to be scored again now that it is in the hull
+    fn take_watchers(&mut self, c: usize) -> Vec<usize> {
+        let watchers = std::mem::take(&mut self.watchers[c]);
+        watchers
+            .into_iter()
+            .filter(|&(a, version)| version == self.version[a])
+            .map(|(a, _)| a)
+            .collect()
+    }
+}
+
+// The search radius is twice the mean hull edge length. The lengths are summed in the order
+// the cities joined the hull, in single precision, and every running total is kept, so an
+// insertion only sums again from the edge it splits
+struct SearchRadius {
+    lengths: Vec<f32>,
+    totals: Vec<f32>,
+    // Where each hull city is in lengths
+    joined: Vec<usize>,
+}
+
+impl SearchRadius {
+    fn new(hull: &Tour, points: &[Point]) -> Self {
+        let mut radius = SearchRadius {
+            lengths: Vec::with_capacity(points.len()),
+            totals: Vec::with_capacity(points.len()),
+            joined: vec![usize::MAX; points.len()],
+        };
+        for &city in hull.cities() {
+            radius.joined[city] = radius.lengths.len();
+            radius
+                .lengths
+                .push(edge_length(points[city], points[hull.next(city)]));
+        }
+        radius.sum_from(0);
+        radius
+    }
+
+    // c went in between a and b
+    fn insert(&mut self, points: &[Point], a: usize, c: usize, b: usize) {
+        let at = self.joined[a];
+        self.lengths[at] = edge_length(points[a], points[c]);
+        self.joined[c] = self.lengths.len();
+        self.lengths.push(edge_length(points[c], points[b]));
+        self.sum_from(at);
+    }
+
+    fn sum_from(&mut self, at: usize) {
+        self.totals.resize(self.lengths.len(), 0.0);
+        let mut total = if at == 0 { 0.0 } else { self.totals[at - 1] };
+        for (length, running) in self.lengths[at..].iter().zip(&mut self.totals[at..]) {
+            total += length;
+            *running = total;
+        }
+    }
+
+    fn get(&self) -> f32 {
+        let len = self.lengths.len();
+        if len < 3 {
+            return 100.0; // Default fallback
+        }
+        let avg_edge_length = self.totals[len - 1] / len as f32;
+        return avg_edge_length * 2.0;
+    }
+}
+
+fn edge_length(a: Point, b: Point) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Inserts every city left in spatial_grid into hull. Each hull edge keeps its best candidate
+/// in a priority queue, and an insertion only scores again the two edges it creates, the
+/// edges that looked at the inserted city and the edges whose candidates the new search
+/// radius changes. That gives the same insertions as scoring every edge for every point: the
+/// best score goes in, equal scores go to the edge starting at the lowest city, and when no
+/// edge has a candidate within the search radius the closest pair goes in, see closest_pair.
+/// With batch above 1 up to that many insertions on edges that do not affect each other go in
+/// per round and the edges they touch are scored in parallel, but the single best point no
+/// longer always goes in first. noise above 0 adds uniform noise of up to that fraction of
+/// the median score of the starting hull edges to every score, for a different tour per seed.
+pub fn insert_all(
+    hull: &mut Tour,
+    points: &[Point],
+    spatial_grid: &mut SpatialGrid,
+    insert_log: &mut Vec<InsertPointResult>,
+    adaptive_n: usize,
+    batch: usize,
+    criterion: &dyn InsertionCriterion,
+    noise: f32,
+    metric: Metric,
+    seed: u64,
+    pb: &ProgressBar,
+) {
+    let mut radius = SearchRadius::new(hull, points);
+    let mut queue = EdgeQueue {
+        points,
+        n: adaptive_n,
+        metric,
+        criterion,
+        rand_dif: 0.0,
+        seed,
+        heap: BinaryHeap::with_capacity(hull.len() + spatial_grid.len()),
+        inside: BinaryHeap::new(),
+        outside: BinaryHeap::new(),
+        cells: BinaryHeap::new(),
+        version: vec![0; points.len()],
+        watchers: vec![Vec::new(); points.len()],
+    };
+    let batch = batch.max(1);
+    if noise > 0.0 {
+        queue.rand_dif = noise * queue.median_score(hull, spatial_grid, radius.get());
+    }
+    queue.score_many(hull, spatial_grid, hull.cities(), radius.get());
+    // The hull cities in a grid of their own, see closest_pair
+    let mut hull_grid = None;
+
+    while !spatial_grid.is_empty() {
+        let mut results = queue.pop_batch(hull, batch);
+        if results.is_empty() {
+            results.push(closest_pair(
+                hull,
+                points,
+                spatial_grid,
+                &mut hull_grid,
+                metric,
+            ));
+        }
+
+        let mut rescore = Vec::with_capacity(2 * results.len());
+        for result in &results {
+            let (a, c) = (result.best_a, result.best_c);
+            radius.insert(points, a, c, hull.next(a));
+            update_hull(result, hull, spatial_grid, insert_log);
+            if let Some(hull_grid) = &mut hull_grid {
+                hull_grid.restore_point(c);
+            }
+            pb.inc(1);
+            rescore.extend([a, c]);
+        }
+        for result in &results {
+            rescore.extend(queue.take_watchers(result.best_c));
+        }
+        rescore.extend(queue.outgrown(spatial_grid, radius.get()));
+        rescore.sort_unstable();
+        rescore.dedup();
+        queue.score_many(hull, spatial_grid, &rescore, radius.get());
+    }
+}
+
+// The inner city near the edge (a_city, b_city) with the highest score, looking at no more than
+// n.min(32) of the cities within search_radius
+#[inline(never)]
+fn score_edge(
+    points: &[Point],
+    spatial_grid: &SpatialGrid,
+    a_city: usize,
+    b_city: usize,
+    search_radius: f32,
+    n: usize,
+    metric: Metric,
+    criterion: &dyn InsertionCriterion,
+    rand_dif: f32,
+    seed: u64,
+) -> Scored {
+    let a = points[a_city];
+    let b = points[b_city];
+
+    // Get candidates from spatial grid using edge-based query
+    let scan = spatial_grid.scan_edge_candidates(a, b, search_radius, n.min(32));
+    if scan.candidates.is_empty() {
+        return Scored { entry: None, scan };
+    }
+
+    // Process candidates in SIMD chunks
+    let chunk_size = 8;
+    let mut edge_best = InsertPointResult {
+        lda: -1.0,
+        best_a: a_city,
+        best_c: usize::MAX,
+    };
+
+    // Precompute edge constants for SIMD
+    let a_x_simd = SimdF32::splat(a.x);
+    let a_y_simd = SimdF32::splat(a.y);
+    let b_x_simd = SimdF32::splat(b.x);
+    let b_y_simd = SimdF32::splat(b.y);
+    // Seeded by the edge alone, so the score never depends on when the edge comes up
+    let mut rng = StdRng::seed_from_u64(shared::mix_seed(
+        shared::mix_seed(seed, a_city as u64),
+        b_city as u64,
+    ));
+
+    for chunk in scan.candidates.chunks(chunk_size) {
+        let len = chunk.len();
+
+        // Load chunk into SIMD vectors
+        let mut c_x_arr = [0.0; 8];
+        let mut c_y_arr = [0.0; 8];
+        for i in 0..len {
+            c_x_arr[i] = points[chunk[i]].x;
+            c_y_arr[i] = points[chunk[i]].y;
+        }
+        let c_x = SimdF32::from_array(c_x_arr);
+        let c_y = SimdF32::from_array(c_y_arr);
+        // Score this edge with all candidates in the chunk
+        let scores = criterion.score(
+            a_x_simd, a_y_simd, b_x_simd, b_y_simd, c_x, c_y, &mut rng, rand_dif, metric,
+        );
+
+        // Find best in this chunk and update edge_best if better
+        for lane in 0..len {
+            if scores[lane] > edge_best.lda {
+                edge_best.lda = scores[lane];
+                edge_best.best_c = chunk[lane];
+            }
+        }
+    }
+
+    // Only a positive score counts as an insertion, the closest pair goes in otherwise
+    let entry = (edge_best.lda > 0.0).then_some(EdgeEntry {
+        result: edge_best,
+        next: b_city,
+        version: 0,
+    });
+    Scored { entry, scan }
+}
+
+// The best candidate of an edge, if any, and what the grid search for it looked at
+struct Scored {
+    entry: Option<EdgeEntry>,
+    scan: EdgeScan,
+}
+
+// The inner/hull pair that goes in when no edge has a candidate, the grid holds exactly the
+// inner points that are left. Every hull city is paired with the inner city nearest to it in
+// a straight line, and the shortest of those pairs under the metric wins, equal distances
+// going to the hull city that joined first. Under GEO the nearest city in a straight line is
+// not always the nearest on the sphere, so that need not be the closest pair under the metric.
+// hull_grid is built the first time it is needed and insert_all keeps it up to date after that
+fn closest_pair<'a>(
+    hull: &Tour,
+    points: &'a [Point],
+    spatial_grid: &SpatialGrid,
+    hull_grid: &mut Option<SpatialGrid<'a>>,
+    metric: Metric,
+) -> InsertPointResult {
+    let mut best_fallback = InsertPointResult {
+        lda: 0.1, // Small positive value to ensure insertion
+        best_a: usize::MAX,
+        best_c: usize::MAX,
+    };
+    let planar = metric.is_planar();
+    let inner = spatial_grid.cities();
+    // With few cities left the ring search around every hull city would walk far across an
+    // almost empty grid, looking the other way round is cheaper
+    let few_inner = inner.len() < hull.len();
+    if planar && few_inner && hull_grid.is_none() {
+        // Built over the inner cities too, so each has room to come back once it joins
+        let mut all = hull.cities().to_vec();
+        all.extend_from_slice(&inner);
+        let mut grid = SpatialGrid::new(points, &all);
+        for &city in &inner {
+            grid.remove_point(city);
+        }
+        *hull_grid = Some(grid);
+    }
+    let hull_grid = hull_grid.as_ref().filter(|_| planar && few_inner);
+
+    // The planar metrics grow with the straight-line distance, so only hull cities about as
+    // close to an inner city as the closest pair in a straight line can win. The slack covers
+    // the metric rounding differently from the straight-line distance
+    let reach = if planar {
+        straight_line_closest(hull, points, spatial_grid, &inner, hull_grid) * (1.0 + 1e-5)
+    } else {
+        f32::INFINITY
+    };
+    let near = hull_grid.map(|hull_grid| {
+        let mut near = vec![false; points.len()];
+        for &inner_city in &inner {
+            for hull_city in hull_grid.query_radius(points[inner_city], reach) {
+                near[hull_city] = true;
+            }
+        }
+        near
+    });
+
+    let mut min_distance = f32::INFINITY;
+    for &hull_city in hull.cities() {
+        if near.as_ref().is_some_and(|near| !near[hull_city]) {
+            continue;
+        }
+        let p = points[hull_city];
+        let inner_city = if few_inner && !planar {
+            // The city the grid search would find, equal distances going to the lowest city
+            let dist_sq =
+                |city: usize| (points[city].x - p.x).powi(2) + (points[city].y - p.y).powi(2);
+            inner
+                .iter()
+                .copied()
+                .min_by(|&x, &y| dist_sq(x).total_cmp(&dist_sq(y)).then(x.cmp(&y)))
+        } else {
+            spatial_grid.nearest_within(p, reach)
+        };
+        let Some(inner_city) = inner_city else {
+            continue;
+        };
+        let distance = metric.dist(points[inner_city], p);
+        if distance < min_distance {
+            min_distance = distance;
+            best_fallback.best_a = hull_city;
+            best_fallback.best_c = inner_city;
+        }
+    }
+    return best_fallback;
+}
+
+// The straight-line distance of the closest inner/hull pair. Every city of the smaller side
+// searches a grid of the other side, never further than the best pair so far, so the searches
+// stay short once a close pair has turned up
+fn straight_line_closest(
+    hull: &Tour,
+    points: &[Point],
+    spatial_grid: &SpatialGrid,
+    inner: &[usize],
+    hull_grid: Option<&SpatialGrid>,
+) -> f32 {
+    let mut min_distance = f32::INFINITY;
+    let mut consider = |hull_city: usize, inner_city: usize| {
+        let (a, c) = (points[hull_city], points[inner_city]);
+        let distance = ((c.x - a.x).powi(2) + (c.y - a.y).powi(2)).sqrt();
+        min_distance = min_distance.min(distance);
+        min_distance
+    };
+
+    let mut radius = f32::INFINITY;
+    if let Some(hull_grid) = hull_grid {
+        for &inner_city in inner {
+            if let Some(hull_city) = hull_grid.nearest_within(points[inner_city], radius) {
+                radius = consider(hull_city, inner_city);
+            }
+        }
+    } else {
+        for &hull_city in hull.cities() {
+            if let Some(inner_city) = spatial_grid.nearest_within(points[hull_city], radius) {
+                radius = consider(hull_city, inner_city);
+            }
+        }
+    }
+    return min_distance;
+}
+
+fn update_hull(
+    result: &InsertPointResult,
+    hull: &mut Tour,
+    spatial_grid: &mut SpatialGrid,
+    insert_log: &mut Vec<InsertPointResult>,
+) {
+    // Log this insertion for potential post-processing
+    insert_log.push(*result);
+
+    // Remove from spatial grid
+    spatial_grid.remove_point(result.best_c);
+
+    // Insert the point after best_a in the hull
+    hull.insert_after(result.best_a, result.best_c);
+}
@@ -0,0 +1,90 @@
+use crate::math;
+use crate::matrix::DistanceMatrix;
+use crate::metric::Metric;
+use crate::shared::Point;
+use rustc_hash::FxHashMap as HashMap;
+
+/// A problem to solve, the cities together with the node numbers they were given in the input
+#[derive(Debug, Clone, Default)]
+pub struct Instance {
+    pub name: String,
+    /// TSPLIB node number of each city, `node_ids[i]` belongs to `points[i]`
+    pub node_ids: Vec<usize>,
+    pub points: Vec<Point>,
+    /// The EDGE_WEIGHT_TYPE distance function between the points
+    pub metric: Metric,
+    /// Distances given explicitly by an EDGE_WEIGHT_SECTION, in which case there are no points
+    pub matrix: Option<DistanceMatrix>,
+}
+
+impl Instance {
+    /// Builds an instance numbering the points 1..=n like a TSPLIB file would
+    pub fn from_points(name: &str, points: Vec<Point>) -> Self {
+        Instance {
+            name: name.to_string(),
+            node_ids: (1..=points.len()).collect(),
+            points,
+            metric: Metric::Euc2d,
+            matrix: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.node_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.node_ids.is_empty()
+    }
+
+    pub fn has_coordinates(&self) -> bool {
+        self.matrix.is_none()
+    }
+
+    /// Maps a tour of indices into `points` to the original node numbers
+    pub fn tour_ids(&self, tour: &[usize]) -> Vec<usize> {
+        tour.iter().map(|&i| self.node_ids[i]).collect()
+    }
+
+    /// Maps a tour given as node numbers back to indices, checking that it visits every
+    /// city exactly once
+    pub fn tour_from_ids(&self, ids: &[usize]) -> Result<Vec<usize>, String> {
+        let index: HashMap<usize, usize> = self
+            .node_ids
+            .iter()
+            .enumerate()
+            .map(|(i, &id)| (id, i))
+            .collect();
+        let mut seen = vec![false; self.len()];
+        let mut tour = Vec::with_capacity(ids.len());
+        for &id in ids {
+            let Some(&i) = index.get(&id) else {
+                return Err(format!("node {} is not in the problem", id));
+            };
+            if seen[i] {
+                return Err(format!("node {} is visited more than once", id));
+            }
+            seen[i] = true;
+            tour.push(i);
+        }
+        if tour.len() != self.len() {
+            return Err(format!(
+                "the tour visits {} of the {} cities",
+                tour.len(),
+                self.len()
+            ));
+        }
+        Ok(tour)
+    }
+
+    /// Length of a tour of indices with every edge rounded by the TSPLIB rules
+    pub fn tsplib_tour_length(&self, tour: &[usize]) -> i64 {
+        match &self.matrix {
+            Some(matrix) => matrix.tsplib_tour_length(tour),
+            None => {
+                let points: Vec<Point> = tour.iter().map(|&i| self.points[i]).collect();
+                math::tsplib_path_dist(&points, self.metric)
+            }
+        }
+    }
+}
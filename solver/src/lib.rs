@@ -0,0 +1,28 @@
+#![feature(portable_simd)]
+#![allow(clippy::needless_return, clippy::too_many_arguments)]
+pub mod candidates;
+pub mod constructor;
+pub mod criterion;
+pub mod edges;
+pub mod error;
+pub mod ils;
+pub mod insertion;
+pub mod instance;
+pub mod lin_kernighan;
+pub mod math;
+pub mod matrix;
+pub mod metric;
+pub mod or_opt;
+pub mod precompute;
+pub mod reader;
+pub mod relp;
+pub mod shared;
+pub mod solver;
+pub mod three_opt;
+pub mod tour;
+pub mod two_opt;
+
+pub use instance::Instance;
+pub use metric::Metric;
+pub use shared::Point;
+pub use solver::{PhaseTimings, Solution, Solver, SolverConfig};
@@ -0,0 +1,219 @@
+// Lin-Kernighan style variable-depth search over candidate neighbour lists. A move is a chain
+// of 2-opt moves that all share the city t1: (t1, t2) goes, t2 joins t3, (t3, t4) goes and
+// the tour is closed with (t4, t1), which the next step removes again with t4 as the new t2
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::candidates::Candidates;
+use crate::metric::Metric;
+use crate::shared::Point;
+use crate::tour::ArrayTour;
+use crate::two_opt::MIN_GAIN;
+
+// Longest chain of 2-opt moves tried from one t1
+const MAX_DEPTH: usize = 50;
+// How many choices of the first t3 are tried before giving up on t1, deeper steps only
+// follow the best one
+const BREADTH: usize = 5;
+// Cities searched between two looks at the clock
+const CLOCK_INTERVAL: usize = 64;
+
+/// Applies improving chains of 2-opt moves until none is left or the deadline passes. Each
+/// step joins the current end t2 to the candidate t3 with the best gain after also removing
+/// (t3, t4), as long as the gain so far stays positive, and never removes an edge the chain
+/// added or adds one it removed. The chain is cut back to its best closed tour, which is only
+/// kept when it is shorter. A city is only looked at again once one of its edges changed.
+/// Returns how much shorter the tour got.
+pub fn lin_kernighan(
+    tour: &mut ArrayTour,
+    points: &[Point],
+    metric: Metric,
+    candidates: &Candidates,
+    deadline: Option<Instant>,
+) -> f64 {
+    let start = tour.order().to_vec();
+    return lin_kernighan_from(tour, points, metric, candidates, &start, deadline);
+}
+
+/// The same search starting from the cities of start only
+pub fn lin_kernighan_from(
+    tour: &mut ArrayTour,
+    points: &[Point],
+    metric: Metric,
+    candidates: &Candidates,
+    start: &[usize],
+    deadline: Option<Instant>,
+) -> f64 {
+    let n = tour.len();
+    if n < 8 {
+        return 0.0;
+    }
+    let d = |a: usize, b: usize| metric.dist(points[a], points[b]) as f64;
+
+    let mut queued = vec![false; n];
+    let mut queue = VecDeque::with_capacity(start.len());
+    for &city in start {
+        if !queued[city] {
+            queued[city] = true;
+            queue.push_back(city);
+        }
+    }
+    let mut total_gain = 0.0;
+    let mut searched = 0;
+
+    while let Some(t1) = queue.pop_front() {
+        queued[t1] = false;
+        searched += 1;
+        if searched % CLOCK_INTERVAL == 0 && deadline.is_some_and(|d| Instant::now() >= d) {
+            break;
+        }
+
+        for t2 in [tour.next(t1), tour.prev(t1)] {
+            let Some((gain, changed)) = improve(tour, candidates, &d, t1, t2) else {
+                continue;
+            };
+            for city in changed {
+                if !queued[city] {
+                    queued[city] = true;
+                    queue.push_back(city);
+                }
+            }
+            total_gain += gain;
+            break;
+        }
+    }
+    return total_gain;
+}
+
+// Tries the best few first steps from (t1, t2), returning the gain and the cities whose
+// edges changed when one of them led to a shorter tour
+fn improve(
+    tour: &mut ArrayTour,
+    candidates: &Candidates,
+    d: &impl Fn(usize, usize) -> f64,
+    t1: usize,
+    t2: usize,
+) -> Option<(f64, Vec<usize>)> {
+    let mut chain = Chain {
+        t1,
+        gain: d(t1, t2),
+        added: Vec::new(),
+        removed: vec![edge(t1, t2)],
+        flips: Vec::new(),
+    };
+    let mut first_steps = chain.steps(tour, candidates, d, t2);
+    first_steps.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    first_steps.truncate(BREADTH);
+
+    for (_, t3, t4) in first_steps {
+        chain.push(tour, d, t2, t3, t4);
+        let (mut best_gain, mut best_len) = (chain.gain - d(t4, t1), 1);
+
+        let mut end = t4;
+        while chain.flips.len() < MAX_DEPTH {
+            let steps = chain.steps(tour, candidates, d, end);
+            let Some(&(_, t3, t4)) = steps
+                .iter()
+                .max_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(&a.1)))
+            else {
+                break;
+            };
+            chain.push(tour, d, end, t3, t4);
+            let closed = chain.gain - d(t4, t1);
+            if closed > best_gain {
+                (best_gain, best_len) = (closed, chain.flips.len());
+            }
+            end = t4;
+        }
+
+        if best_gain > MIN_GAIN {
+            chain.undo(tour, best_len);
+            let changed = chain.flips.iter().flatten().copied().collect();
+            return Some((best_gain, changed));
+        }
+        chain.undo(tour, 0);
+        chain.gain = d(t1, t2);
+        chain.added.clear();
+        chain.removed.truncate(1);
+    }
+    None
+}
+
+// The 2-opt moves applied so far from t1, with the edges they added and removed
+struct Chain {
+    t1: usize,
+    /// Length of the removed edges minus the added ones, not counting the closing (t4, t1)
+    gain: f64,
+    added: Vec<(usize, usize)>,
+    removed: Vec<(usize, usize)>,
+    flips: Vec<[usize; 4]>,
+}
+
+impl Chain {
+    // Every (gain after removing (t3, t4), t3, t4) that may follow with t2 as the end
+    fn steps(
+        &self,
+        tour: &ArrayTour,
+        candidates: &Candidates,
+        d: &impl Fn(usize, usize) -> f64,
+        t2: usize,
+    ) -> Vec<(f64, usize, usize)> {
+        // Read the tour so t2 follows t1, t4 has to come before t3 for the move to close
+        let forward = tour.next(self.t1) == t2;
+        let succ = if forward {
+            tour.next(t2)
+        } else {
+            tour.prev(t2)
+        };
+
+        let mut steps = Vec::new();
+        for &t3 in candidates.of(t2) {
+            let g = self.gain - d(t2, t3);
+            // Lists are sorted, no later candidate keeps the gain positive either
+            if g <= MIN_GAIN {
+                break;
+            }
+            if t3 == self.t1 || t3 == succ || self.removed.contains(&edge(t2, t3)) {
+                continue;
+            }
+            let t4 = if forward {
+                tour.prev(t3)
+            } else {
+                tour.next(t3)
+            };
+            if self.added.contains(&edge(t3, t4)) {
+                continue;
+            }
+            steps.push((g + d(t3, t4), t3, t4));
+        }
+        steps
+    }
+
+    fn push(
+        &mut self,
+        tour: &mut ArrayTour,
+        d: &impl Fn(usize, usize) -> f64,
+        t2: usize,
+        t3: usize,
+        t4: usize,
+    ) {
+        tour.two_opt_move(self.t1, t2, t3, t4);
+        self.gain += d(t3, t4) - d(t2, t3);
+        self.added.push(edge(t2, t3));
+        self.removed.push(edge(t3, t4));
+        self.flips.push([self.t1, t2, t3, t4]);
+    }
+
+    // Takes back every 2-opt move after the first len, latest first
+    fn undo(&mut self, tour: &mut ArrayTour, len: usize) {
+        while self.flips.len() > len {
+            let [t1, t2, t3, t4] = self.flips.pop().unwrap();
+            tour.two_opt_move(t1, t4, t3, t2);
+        }
+    }
+}
+
+fn edge(a: usize, b: usize) -> (usize, usize) {
+    if a < b { (a, b) } else { (b, a) }
+}
@@ -1,334 +1,310 @@
-#![feature(portable_simd)]
 #![feature(duration_millis_float)]
-use indicatif::ProgressBar;
-use rayon::prelude::*;
-use std::env;
-use std::fs;
-use std::time::Instant;
+use std::time::Duration;
 
-use crate::precompute::{SpatialGrid, calculate_search_radius};
-use crate::reader::{
-    no_post, should_edge_swap, should_log, should_or_opt, should_relp, write_to_tsp_file,
-};
-use crate::relp::{find_lowest_lda_points, remove_points_from_hull};
-use crate::shared::SimdF32;
-mod edges;
-mod math;
-mod or_opt;
-mod precompute;
-mod reader;
-mod relp;
-mod shared;
+use tsp_rust::constructor::ConstructorKind;
+use tsp_rust::error::{EXIT_INVALID_TOUR, EXIT_IO, EXIT_USAGE, ReadError};
+use tsp_rust::reader::{self, OutputFormat};
+use tsp_rust::{Instance, Solution, Solver, SolverConfig, math};
 
-#[inline(never)]
-fn insert_point(
-    hull: &[shared::Point],
-    spatial_grid: &SpatialGrid,
-    n: usize,
-    rand_dif: f32,
-) -> relp::InsertPointResult {
-    let hull_len = hull.len();
-    let search_radius = calculate_search_radius(hull);
+mod cli;
 
-    // Process hull edges in parallel
-    (0..hull_len)
-        .into_par_iter()
-        .map(|j| {
-            let a = hull[j];
-            let b = hull[(j + 1) % hull_len];
+use cli::{BenchArgs, Command, ConvertArgs, SolveArgs};
 
-            // Get candidates from spatial grid using edge-based query
-            let candidates = spatial_grid.query_edge_candidates(a, b, search_radius);
-
-            // Filter to only include points that are actually in the spatial grid
-            // (i.e., haven't been removed yet - represents inner_points)
-            let mut valid_candidates = Vec::with_capacity(candidates.len().min(n));
-            for point in candidates {
-                if spatial_grid.contains_point(point) {
-                    valid_candidates.push(point);
-                    // Early termination if we have enough candidates
-                    if valid_candidates.len() >= n.min(32) {
-                        break;
-                    }
-                }
-            }
-
-            if valid_candidates.is_empty() {
-                return relp::InsertPointResult {
-                    lda: -1.0,
-                    best_a: shared::Point { x: 0.0, y: 0.0 },
-                    best_c: shared::Point { x: 0.0, y: 0.0 },
-                };
-            }
-
-            // Process candidates in SIMD chunks
-            let chunk_size = 8;
-            let mut edge_best = relp::InsertPointResult {
-                lda: -1.0,
-                best_a: a,
-                best_c: shared::Point { x: 0.0, y: 0.0 },
-            };
-
-            // Precompute edge constants for SIMD
-            let a_x_simd = SimdF32::splat(a.x);
-            let a_y_simd = SimdF32::splat(a.y);
-            let b_x_simd = SimdF32::splat(b.x);
-            let b_y_simd = SimdF32::splat(b.y);
-
-            for chunk in valid_candidates.chunks(chunk_size) {
-                let len = chunk.len();
-
-                // Load chunk into SIMD vectors
-                let mut c_x_arr = [0.0; 8];
-                let mut c_y_arr = [0.0; 8];
-                for i in 0..len {
-                    c_x_arr[i] = chunk[i].x;
-                    c_y_arr[i] = chunk[i].y;
-                }
-                let c_x = SimdF32::from_array(c_x_arr);
-                let c_y = SimdF32::from_array(c_y_arr);
-                let mut rng = rand::thread_rng();
-                // Calculate LDA for this edge with all candidates in the chunk
-                let curr_lda = math::lda(
-                    a_x_simd, a_y_simd, b_x_simd, b_y_simd, c_x, c_y, &mut rng, 0.0,
-                );
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(err) => {
+            fail(err, EXIT_USAGE);
+        }
+    };
+
+    match command {
+        Command::Help(command) => print!("{}", cli::help(command)),
+        Command::Solve(args) => solve(args),
+        Command::Validate { input, tour } => validate(&input, &tour),
+        Command::Stats { input } => stats(&input),
+        Command::Convert(args) => convert(args),
+        Command::Bench(args) => bench(args),
+    }
+}
 
-                // Find best in this chunk and update edge_best if better
-                for lane in 0..len {
-                    if curr_lda[lane] > edge_best.lda {
-                        edge_best.lda = curr_lda[lane];
-                        edge_best.best_a = a;
-                        edge_best.best_c = chunk[lane];
-                    }
-                }
-            }
+fn build_thread_pool(threads: Option<usize>) {
+    let mut builder = rayon::ThreadPoolBuilder::new();
+    if let Some(threads) = threads {
+        builder = builder.num_threads(threads);
+    }
+    builder.build_global().unwrap();
+}
 
-            edge_best
-        })
-        .reduce(
-            || relp::InsertPointResult {
-                lda: -1.0,
-                best_a: shared::Point { x: 0.0, y: 0.0 },
-                best_c: shared::Point { x: 0.0, y: 0.0 },
-            },
-            |a, b| if a.lda > b.lda { a } else { b },
-        )
+// Reports err on stderr and exits with code, see the exit codes in cli::HELP
+fn fail(err: impl std::fmt::Display, code: i32) -> ! {
+    eprintln!("error: {}", err);
+    std::process::exit(code);
 }
 
-fn update_hull(
-    result: &relp::InsertPointResult,
-    hull: &mut Vec<shared::Point>,
-    inner_hull: &mut Vec<shared::Point>,
-    spatial_grid: &mut SpatialGrid,
-    insert_log: &mut Vec<relp::InsertPointResult>,
-) {
-    // Log this insertion for potential post-processing
-    insert_log.push(result.clone());
+fn read_instance(input: &str) -> Instance {
+    match reader::read_source(input) {
+        Ok(instance) => instance,
+        Err(err) => fail(describe(input, &err), err.exit_code()),
+    }
+}
 
-    // Remove the inserted point from the inner point set
-    if let Some(pos) = inner_hull.iter().position(|&p| p == result.best_c) {
-        inner_hull.remove(pos);
+// I/O errors already name what could not be read, parse errors need the input in front
+fn describe(input: &str, err: &ReadError) -> String {
+    match err {
+        ReadError::Io { .. } => err.to_string(),
+        ReadError::Parse(_) if input == "-" => format!("stdin: {}", err),
+        ReadError::Parse(_) => format!("{}: {}", input, err),
     }
+}
 
-    // Remove from spatial grid
-    spatial_grid.remove_point(result.best_c);
+fn write_output(instance: &Instance, tour: &[usize], path: &str, format: OutputFormat) {
+    if let Err(err) = reader::write_output(instance, tour, path, format) {
+        fail(format!("could not write {}: {}", path, err), EXIT_IO);
+    }
+}
 
-    // Insert the point after best_a in the hull
-    if let Some(pos) = hull.iter().position(|&p| p == result.best_a) {
-        hull.insert(pos + 1, result.best_c);
+// Explicit matrices have no coordinates to write, so they default to a tour file
+fn resolve_format(format: Option<OutputFormat>, instance: &Instance) -> OutputFormat {
+    match format {
+        Some(OutputFormat::Tsp) if !instance.has_coordinates() => fail(
+            "--format tsp needs coordinates, this instance only has EDGE_WEIGHT_SECTION",
+            EXIT_USAGE,
+        ),
+        Some(format) => format,
+        None if instance.has_coordinates() => OutputFormat::Tsp,
+        None => OutputFormat::Tour,
     }
 }
 
-fn get_output_path() -> String {
-    // Try to determine the correct output path
-    if std::path::Path::new("backend/output").exists() {
-        "backend/output/OUT.tsp".to_string()
-    } else if std::path::Path::new("output").exists() {
-        "output/OUT.tsp".to_string()
-    } else {
-        // Create the directory if it doesn't exist
-        std::fs::create_dir_all("backend/output").unwrap_or_else(|_| {
-            std::fs::create_dir_all("output").unwrap();
-        });
-        if std::path::Path::new("backend/output").exists() {
-            "backend/output/OUT.tsp".to_string()
-        } else {
-            "output/OUT.tsp".to_string()
-        }
+// EXPLICIT instances are only solved by nearest neighbour, 2-opt and or-opt, a flag asking
+// for anything else would be left out without a word
+fn check_matrix_config(input: &str, instance: &Instance, config: &SolverConfig) {
+    if instance.matrix.is_none() {
+        return;
+    }
+    let default = SolverConfig::default();
+    let unsupported = [
+        ("--three-opt", config.three_opt),
+        ("--relp-first", config.relp_first),
+        ("--lk", config.lin_kernighan),
+        ("--lk-time", config.lk_time.is_some()),
+        ("--time-limit", config.time_limit.is_some()),
+        ("--starts", config.starts > 1),
+        ("--noise", config.noise != default.noise),
+        ("--batch", config.insert_batch != default.insert_batch),
+        (
+            "--constructor",
+            !matches!(
+                config.constructor,
+                ConstructorKind::Insertion | ConstructorKind::NearestNeighbour
+            ),
+        ),
+        ("--criterion", config.criterion != default.criterion),
+        ("--candidates", config.candidates != default.candidates),
+    ];
+    if let Some((flag, _)) = unsupported.iter().find(|(_, given)| *given) {
+        fail(
+            format!(
+                "{}: {} does not apply to EXPLICIT instances, they are solved by nearest \
+                 neighbour, 2-opt and or-opt",
+                input, flag
+            ),
+            EXIT_USAGE,
+        );
     }
 }
 
-fn main() {
-    rayon::ThreadPoolBuilder::new().build_global().unwrap();
-    
-    let points: Vec<shared::Point> = reader::parse_file(&reader::read_file());
+fn read_tour(instance: &Instance, path: &str) -> Vec<usize> {
+    let ids = match reader::read_tour(path) {
+        Ok(ids) => ids,
+        Err(err) => fail(describe(path, &err), err.exit_code()),
+    };
+    match instance.tour_from_ids(&ids) {
+        Ok(tour) => tour,
+        Err(err) => fail(
+            format!("{} is not a valid tour: {}", path, err),
+            EXIT_INVALID_TOUR,
+        ),
+    }
+}
 
-    let start = Instant::now();
+fn solve(args: SolveArgs) {
+    build_thread_pool(args.threads);
+    let instance = read_instance(&args.input);
+    let format = resolve_format(args.format, &instance);
+    check_matrix_config(&args.input, &instance, &args.config);
 
-    // Build spatial grid instead of kdtree
-    let mut spatial_grid = SpatialGrid::new(&points);
+    // Everything but the result goes to stderr, so -o - leaves stdout clean for it, and
+    // --no-log leaves stderr empty unless something goes wrong
+    let sl = !args.config.log;
 
-    let mut hull = math::convex_hull(&points);
-    let mut inner_hull = reader::vec_diff(&points, &hull);
-    let mut insert_log: Vec<relp::InsertPointResult> = Vec::with_capacity(inner_hull.len());
+    let config = args.config;
+    let solution = Solver::new(config).solve_instance(&instance);
 
-    if hull.len() == 0 {
-        eprintln!(
-            "Hull length is zero, input was not read properly, args are {:#?}",
-            env::args().collect::<Vec<_>>()
-        );
-        if let Some(arg) = env::args().nth(1) {
-            eprintln!("File is {:?}", fs::read_to_string(arg));
-        } else {
-            eprintln!("No argument provided");
+    if !sl {
+        eprintln!("{:?}", solution.construction_length);
+        eprintln!("Elapsed: {:.2?}", solution.timings.construction);
+        if solution.start_lengths.len() > 1 {
+            eprintln!("{}", start_spread(&solution.start_lengths));
         }
-        std::process::exit(1);
     }
 
-    // Remove hull points from spatial grid since they're not "inner" points
-    for &hull_point in &hull {
-        spatial_grid.remove_point(hull_point);
+    if !config.post {
+        if !sl {
+            eprintln!("Operation completed, written to file");
+        }
+        write_output(&instance, &solution.tour, &args.output, format);
+        std::process::exit(0);
     }
 
-    let sl = should_log();
-    let mut pb: ProgressBar = ProgressBar::new(1);
-    if sl {
-        println!("Logging disabled");
-    }
+    let o_end = solution.timings.post_processing().as_millis_f32();
     if !sl {
-        pb = ProgressBar::new(inner_hull.len().try_into().unwrap());
+        eprintln!(
+            "Improved the tour to dist of {} with a {:.2?}% improvement using {:.2?} seconds",
+            solution.tsplib_length,
+            (solution.construction_length / solution.length) - 1.0,
+            o_end / 1000.0
+        );
     }
 
-    let adaptive_n = (64_usize).min(inner_hull.len() / 10).max(8);
-
-    let max_iterations = inner_hull.len() * 2; // Safety margin
-    let mut iteration_count = 0;
-
-    while !inner_hull.is_empty() && iteration_count < max_iterations {
-        iteration_count += 1;
-
-        let result = insert_point(&hull, &spatial_grid, adaptive_n, 0.0);
-
-        // If no valid insertion found, try fallback strategy
-        if result.lda <= 0.0 {
-            // Find the closest point to any hull point as fallback
-            let mut best_fallback = relp::InsertPointResult {
-                lda: 0.1, // Small positive value to ensure insertion
-                best_a: hull[0],
-                best_c: inner_hull[0],
-            };
+    write_output(&instance, &solution.tour, &args.output, format);
+}
 
-            let mut min_distance = f32::INFINITY;
-            for &inner_point in &inner_hull {
-                for (_i, &hull_point) in hull.iter().enumerate() {
-                    let dx = inner_point.x - hull_point.x;
-                    let dy = inner_point.y - hull_point.y;
-                    let distance = (dx * dx + dy * dy).sqrt();
+fn validate(input: &str, tour_path: &str) {
+    let instance = read_instance(input);
+    let tour = read_tour(&instance, tour_path);
+    println!(
+        "Valid tour of {} cities, length {}",
+        tour.len(),
+        instance.tsplib_tour_length(&tour)
+    );
+}
 
-                    if distance < min_distance {
-                        min_distance = distance;
-                        best_fallback.best_a = hull_point;
-                        best_fallback.best_c = inner_point;
-                    }
+fn stats(input: &str) {
+    let instance = read_instance(input);
+    println!("Name:             {}", instance.name);
+    println!("Dimension:        {}", instance.len());
+
+    match &instance.matrix {
+        Some(matrix) => {
+            println!("Edge weight type: EXPLICIT");
+            let n = matrix.len();
+            let mut min = f32::INFINITY;
+            let mut max = f32::NEG_INFINITY;
+            let mut sum = 0.0f64;
+            for i in 0..n {
+                for j in (i + 1)..n {
+                    let d = matrix.dist(i, j);
+                    min = min.min(d);
+                    max = max.max(d);
+                    sum += d as f64;
                 }
             }
-
-            update_hull(
-                &best_fallback,
-                &mut hull,
-                &mut inner_hull,
-                &mut spatial_grid,
-                &mut insert_log,
-            );
-        } else {
-            update_hull(
-                &result,
-                &mut hull,
-                &mut inner_hull,
-                &mut spatial_grid,
-                &mut insert_log,
+            let pairs = (n * n.saturating_sub(1) / 2).max(1);
+            println!(
+                "Edge weights:     min {} / mean {:.2} / max {}",
+                min,
+                sum / pairs as f64,
+                max
             );
         }
-
-        if !sl {
-            pb.inc(1);
-        }
-    }
-
-    if iteration_count >= max_iterations {
-        println!(
-            "Hit iteration limit, possible infinite loop detected. Remaining points: {}",
-            inner_hull.len()
-        );
-        // Force exit with remaining points
-        for &remaining_point in &inner_hull {
+        None => {
+            let points = &instance.points;
+            println!("Edge weight type: {}", instance.metric.tsplib_name());
+            let min_x = points.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+            let max_x = points.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+            let min_y = points.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+            let max_y = points.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
             println!(
-                "Unprocessed point: ({}, {})",
-                remaining_point.x, remaining_point.y
+                "Bounding box:     ({}, {}) to ({}, {})",
+                min_x, min_y, max_x, max_y
             );
+            println!(
+                "Convex hull:      {} points",
+                math::convex_hull(points).len()
+            );
+            let mut sorted = points.clone();
+            sorted.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+            sorted.dedup();
+            println!("Duplicate points: {}", points.len() - sorted.len());
         }
     }
+}
 
-    if !sl {
-        pb.finish();
-    }
-
-    let dist = math::path_dist(&hull);
-    let elapsed = start.elapsed();
-    if !sl {
-        println!("{:?}", dist);
-        println!("Elapsed: {:.2?}", elapsed);
-    }
-
-    let o_start = Instant::now();
-
-    // Get consistent output path
-    let output_path = get_output_path();
-
-    if no_post() {
-        println!("Operation completed, written to file");
-        write_to_tsp_file(&hull, &output_path);
-        std::process::exit(0);
-    }
-
-    if !should_edge_swap() {
-        edges::eliminate_all_crossings(&mut hull);
-    }
-    if !should_or_opt() {
-        or_opt::multi_or_opt_optimization(&mut hull);
-    }
-    if !should_relp() {
-        // Rebuild spatial grid for re-optimization phase
-        let mut new_inner_hull = find_lowest_lda_points(&insert_log, hull.len() / 8);
-        let mut reopt_spatial_grid = SpatialGrid::new(&new_inner_hull);
-
-        remove_points_from_hull(&mut hull, &new_inner_hull);
+fn convert(args: ConvertArgs) {
+    let instance = read_instance(&args.input);
+    let format = resolve_format(args.format, &instance);
+    let tour = match &args.tour {
+        Some(path) => read_tour(&instance, path),
+        None => (0..instance.len()).collect(),
+    };
+    write_output(&instance, &tour, &args.output, format);
+}
 
-        while !new_inner_hull.is_empty() {
-            let result = insert_point(&hull, &reopt_spatial_grid, adaptive_n, 0.0);
-            update_hull(
-                &result,
-                &mut hull,
-                &mut new_inner_hull,
-                &mut reopt_spatial_grid,
-                &mut insert_log,
-            );
+fn bench(args: BenchArgs) {
+    build_thread_pool(args.threads);
+    let solver = Solver::new(args.config);
+
+    println!(
+        "{:<16} {:>8} {:>14} {:>14} {:>14} {:>12}",
+        "instance", "cities", "construction", "post", "total", "length"
+    );
+    for input in &args.inputs {
+        let instance = read_instance(input);
+        check_matrix_config(input, &instance, &args.config);
+        let mut best: Option<Solution> = None;
+        for _ in 0..args.runs {
+            let solution = solver.solve_instance(&instance);
+            if best
+                .as_ref()
+                .is_none_or(|b| solution.timings.total() < b.timings.total())
+            {
+                best = Some(solution);
+            }
         }
-    }
-
-    let o_end = o_start.elapsed().as_millis_f32();
-    let new_dist = math::path_dist(&hull);
-    if !sl {
+        let best = best.unwrap();
+        let name = if instance.name.is_empty() {
+            input.as_str()
+        } else {
+            instance.name.as_str()
+        };
         println!(
-            "Improved the tour to dist of {:.2?} with a {:.2?}% improvement using {:.2?} seconds",
-            new_dist,
-            (dist / new_dist) - 1.0,
-            o_end / 1000.0
+            "{:<16} {:>8} {:>14} {:>14} {:>14} {:>12}",
+            name,
+            instance.len(),
+            format_duration(best.timings.construction),
+            format_duration(best.timings.post_processing()),
+            format_duration(best.timings.total()),
+            best.tsplib_length
         );
-    } else {
-        println!("Operation completed, written to file");
+        if best.start_lengths.len() > 1 {
+            println!("    {}", start_spread(&best.start_lengths));
+        }
     }
+}
+
+// How far the tour lengths of several starts spread
+fn start_spread(lengths: &[i64]) -> String {
+    let count = lengths.len() as f64;
+    let mean = lengths.iter().sum::<i64>() as f64 / count;
+    let variance = lengths
+        .iter()
+        .map(|&length| (length as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    format!(
+        "{} starts: best {}, mean {:.1}, worst {}, std dev {:.1} ({:.2}% of the mean)",
+        lengths.len(),
+        lengths.iter().min().unwrap(),
+        mean,
+        lengths.iter().max().unwrap(),
+        variance.sqrt(),
+        100.0 * variance.sqrt() / mean
+    )
+}
 
-    // Always write to the consistent output path
-    write_to_tsp_file(&hull, &output_path);
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2?}", duration)
 }
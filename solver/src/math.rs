@@ -1,9 +1,9 @@
+use crate::metric::Metric;
 use crate::shared::{self};
 use rand::Rng;
-use rand::rngs::ThreadRng;
 use std::simd::cmp::SimdPartialOrd;
 use std::simd::num::SimdFloat;
-use std::simd::{Simd, StdFloat};
+use std::simd::{Select, Simd, StdFloat};
 
 type SimdF32 = Simd<f32, 8>;
 
@@ -72,21 +72,9 @@ pub fn fast_acos(x: SimdF32) -> SimdF32 {
     mask.select(pi - ret, ret)
 }
 
-#[inline(never)]
-pub fn lda(
-    a_x: SimdF32,
-    a_y: SimdF32,
-    b_x: SimdF32,
-    b_y: SimdF32,
-    c_x: SimdF32,
-    c_y: SimdF32,
-    rng: &mut ThreadRng,
-    rand_dif: f32,
-) -> SimdF32 {
-    let ab = calc_dist_simd(a_x, a_y, b_x, b_y);
-    let bc = calc_dist_simd(b_x, b_y, c_x, c_y);
-    let ac = calc_dist_simd(a_x, a_y, c_x, c_y);
-
+// Angle at c between a and c and b and c, from the three side lengths by the cosine law
+#[inline(always)]
+pub fn insertion_angle(ab: SimdF32, bc: SimdF32, ac: SimdF32) -> SimdF32 {
     // Fixed: cosine law calculation (b² + c² - a²) / (2bc)
     let numerator = bc.mul_add(bc, ac * ac) - ab * ab;
     let denominator = SimdF32::splat(2.0) * bc * ac;
@@ -96,50 +84,119 @@ pub fn lda(
     let cosine = cosine.simd_clamp(SimdF32::splat(-1.0), SimdF32::splat(1.0));
 
     // Use SIMD fast_acos approximation
-    let acos = fast_acos(cosine);
+    fast_acos(cosine)
+}
+
+// How far c is from the edge (a, b), given the lengths of the three sides
+#[inline(always)]
+pub fn edge_distance(
+    a_x: SimdF32,
+    a_y: SimdF32,
+    b_x: SimdF32,
+    b_y: SimdF32,
+    c_x: SimdF32,
+    c_y: SimdF32,
+    (ab, bc, ac): (SimdF32, SimdF32, SimdF32),
+    metric: Metric,
+) -> SimdF32 {
+    // Off the plane there is no straight segment to measure against, so use the detour
+    // inserting c between a and b would add, which is zero on the edge just like point_line
+    if metric.is_planar() {
+        point_line(a_x, a_y, b_x, b_y, c_x, c_y)
+    } else {
+        ac + bc - ab
+    }
+}
 
-    let dist = point_line(a_x, a_y, b_x, b_y, c_x, c_y);
+#[inline(never)]
+pub fn lda(
+    a_x: SimdF32,
+    a_y: SimdF32,
+    b_x: SimdF32,
+    b_y: SimdF32,
+    c_x: SimdF32,
+    c_y: SimdF32,
+    rng: &mut impl Rng,
+    rand_dif: f32,
+    metric: Metric,
+) -> SimdF32 {
+    let ab = metric.dist_simd(a_x, a_y, b_x, b_y);
+    let bc = metric.dist_simd(b_x, b_y, c_x, c_y);
+    let ac = metric.dist_simd(a_x, a_y, c_x, c_y);
+    let acos = insertion_angle(ab, bc, ac);
+    let dist = edge_distance(a_x, a_y, b_x, b_y, c_x, c_y, (ab, bc, ac), metric);
 
     // Add small epsilon to avoid division by zero
     let epsilon = SimdF32::splat(1e-10);
     let safe_dist = dist.simd_max(epsilon);
+    return (acos / safe_dist) + noise(rng, rand_dif);
+}
+
+// The same uniform noise in -rand_dif..rand_dif for every lane, none when rand_dif is 0
+#[inline(always)]
+pub fn noise(rng: &mut impl Rng, rand_dif: f32) -> SimdF32 {
     if rand_dif == 0.0 {
-        return acos / safe_dist;
+        return SimdF32::splat(0.0);
     }
-    return (acos / safe_dist) + SimdF32::splat(rng.gen_range(-rand_dif..rand_dif));
+    SimdF32::splat(rng.gen_range(-rand_dif..rand_dif))
 }
 
-pub fn path_dist(path: &[shared::Point]) -> f32 {
-    if path.len() == 0 {
+pub fn path_dist(path: &[shared::Point], metric: Metric) -> f32 {
+    if path.is_empty() {
         panic!("[ERROR] Path dist given an empty path");
     }
     let mut sum = 0.0;
     for i in 0..path.len() - 1 {
-        sum += calc_dist(path[i], path[i + 1]);
+        sum += metric.dist(path[i], path[i + 1]);
     }
-    sum += calc_dist(path[path.len() - 1], path[0]);
+    sum += metric.dist(path[path.len() - 1], path[0]);
+    sum
+}
+
+// Tour length with every edge rounded by the TSPLIB rules, comparable to published optima
+pub fn tsplib_path_dist(path: &[shared::Point], metric: Metric) -> i64 {
+    if path.is_empty() {
+        return 0;
+    }
+    let mut sum = 0;
+    for i in 0..path.len() - 1 {
+        sum += metric.tsplib_dist(path[i], path[i + 1]);
+    }
+    sum += metric.tsplib_dist(path[path.len() - 1], path[0]);
     sum
 }
 
 pub fn convex_hull(points: &[shared::Point]) -> Vec<shared::Point> {
-    let mut points = points.to_vec();
+    return convex_hull_indices(points)
+        .into_iter()
+        .map(|i| points[i])
+        .collect();
+}
 
-    if points.len() <= 1 {
-        return points;
+// Indices of the points on the convex hull, counterclockwise from the lowest leftmost point
+pub fn convex_hull_indices(points: &[shared::Point]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..points.len()).collect();
+
+    if order.len() <= 1 {
+        return order;
     }
 
     // Sort points lexicographically (x, then y)
-    points.sort_by(|a, b| {
+    order.sort_by(|&a, &b| {
+        let (a, b) = (points[a], points[b]);
         a.x.partial_cmp(&b.x)
             .unwrap()
             .then(a.y.partial_cmp(&b.y).unwrap())
     });
 
-    let mut lower = Vec::new();
-    for &p in &points {
+    let cross =
+        |o: usize, a: usize, b: usize| shared::Point::cross(&points[o], &points[a], &points[b]);
+
+    let mut lower: Vec<usize> = Vec::new();
+    for &p in &order {
         while lower.len() >= 2 {
             let l = lower.len();
-            if shared::Point::cross(&lower[l - 2], &lower[l - 1], &p) <= 0.0 {
+            if cross(lower[l - 2], lower[l - 1], p) <= 0.0 {
                 lower.pop();
             } else {
                 break;
@@ -148,11 +205,11 @@ pub fn convex_hull(points: &[shared::Point]) -> Vec<shared::Point> {
         lower.push(p);
     }
 
-    let mut upper = Vec::new();
-    for &p in points.iter().rev() {
+    let mut upper: Vec<usize> = Vec::new();
+    for &p in order.iter().rev() {
         while upper.len() >= 2 {
             let l = upper.len();
-            if shared::Point::cross(&upper[l - 2], &upper[l - 1], &p) <= 0.0 {
+            if cross(upper[l - 2], upper[l - 1], p) <= 0.0 {
                 upper.pop();
             } else {
                 break;
@@ -0,0 +1,163 @@
+// Explicit distance matrices, for instances that give EDGE_WEIGHT_SECTION instead of coordinates
+
+/// A symmetric distance matrix between n cities, stored in full so lookups are a single index
+#[derive(Debug, Clone, Default)]
+pub struct DistanceMatrix {
+    n: usize,
+    data: Vec<f32>,
+}
+
+/// The TSPLIB EDGE_WEIGHT_FORMAT layouts of an EDGE_WEIGHT_SECTION
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeightFormat {
+    FullMatrix,
+    UpperRow,
+    LowerRow,
+    UpperDiagRow,
+    LowerDiagRow,
+    UpperCol,
+    LowerCol,
+    UpperDiagCol,
+    LowerDiagCol,
+}
+
+impl WeightFormat {
+    pub fn from_tsplib(name: &str) -> Option<WeightFormat> {
+        match name {
+            "FULL_MATRIX" => Some(WeightFormat::FullMatrix),
+            "UPPER_ROW" => Some(WeightFormat::UpperRow),
+            "LOWER_ROW" => Some(WeightFormat::LowerRow),
+            "UPPER_DIAG_ROW" => Some(WeightFormat::UpperDiagRow),
+            "LOWER_DIAG_ROW" => Some(WeightFormat::LowerDiagRow),
+            "UPPER_COL" => Some(WeightFormat::UpperCol),
+            "LOWER_COL" => Some(WeightFormat::LowerCol),
+            "UPPER_DIAG_COL" => Some(WeightFormat::UpperDiagCol),
+            "LOWER_DIAG_COL" => Some(WeightFormat::LowerDiagCol),
+            _ => None,
+        }
+    }
+
+    /// How many numbers an EDGE_WEIGHT_SECTION of this layout holds for n cities
+    pub fn value_count(&self, n: usize) -> usize {
+        match self {
+            WeightFormat::FullMatrix => n * n,
+            WeightFormat::UpperRow
+            | WeightFormat::LowerRow
+            | WeightFormat::UpperCol
+            | WeightFormat::LowerCol => n * n.saturating_sub(1) / 2,
+            WeightFormat::UpperDiagRow
+            | WeightFormat::LowerDiagRow
+            | WeightFormat::UpperDiagCol
+            | WeightFormat::LowerDiagCol => n * (n + 1) / 2,
+        }
+    }
+}
+
+impl DistanceMatrix {
+    /// Builds the matrix from the numbers of an EDGE_WEIGHT_SECTION in the given layout.
+    /// `values` must hold exactly `format.value_count(n)` numbers.
+    pub fn from_tsplib(format: WeightFormat, n: usize, values: &[f32]) -> DistanceMatrix {
+        assert_eq!(values.len(), format.value_count(n));
+        let mut matrix = DistanceMatrix {
+            n,
+            data: vec![0.0; n * n],
+        };
+        let mut values = values.iter().copied();
+
+        // A column-wise triangle read down the columns visits the same cells as the opposite
+        // row-wise triangle read along the rows, so only the row layouts need walking
+        match format {
+            WeightFormat::FullMatrix => {
+                matrix.data.copy_from_slice(&values.collect::<Vec<_>>());
+            }
+            WeightFormat::UpperRow | WeightFormat::LowerCol => {
+                for i in 0..n {
+                    for j in (i + 1)..n {
+                        matrix.set(i, j, values.next().unwrap());
+                    }
+                }
+            }
+            WeightFormat::LowerRow | WeightFormat::UpperCol => {
+                for i in 0..n {
+                    for j in 0..i {
+                        matrix.set(i, j, values.next().unwrap());
+                    }
+                }
+            }
+            WeightFormat::UpperDiagRow | WeightFormat::LowerDiagCol => {
+                for i in 0..n {
+                    for j in i..n {
+                        matrix.set(i, j, values.next().unwrap());
+                    }
+                }
+            }
+            WeightFormat::LowerDiagRow | WeightFormat::UpperDiagCol => {
+                for i in 0..n {
+                    for j in 0..=i {
+                        matrix.set(i, j, values.next().unwrap());
+                    }
+                }
+            }
+        }
+        matrix
+    }
+
+    fn set(&mut self, i: usize, j: usize, value: f32) {
+        self.data[i * self.n + j] = value;
+        self.data[j * self.n + i] = value;
+    }
+
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    #[inline(always)]
+    pub fn dist(&self, i: usize, j: usize) -> f32 {
+        self.data[i * self.n + j]
+    }
+
+    pub fn tour_length(&self, tour: &[usize]) -> f32 {
+        let n = tour.len();
+        (0..n).map(|i| self.dist(tour[i], tour[(i + 1) % n])).sum()
+    }
+
+    // Explicit weights are integers in TSPLIB, so rounding each one gives the exact length
+    pub fn tsplib_tour_length(&self, tour: &[usize]) -> i64 {
+        let n = tour.len();
+        (0..n)
+            .map(|i| self.dist(tour[i], tour[(i + 1) % n]).round() as i64)
+            .sum()
+    }
+}
+
+// Nearest neighbour tour starting from city 0
+pub fn nearest_neighbour_tour(matrix: &DistanceMatrix) -> Vec<usize> {
+    let n = matrix.len();
+    if n == 0 {
+        return vec![];
+    }
+    let mut visited = vec![false; n];
+    let mut tour = Vec::with_capacity(n);
+    let mut current = 0;
+    visited[0] = true;
+    tour.push(0);
+
+    for _ in 1..n {
+        let mut best = usize::MAX;
+        let mut best_dist = f32::INFINITY;
+        for (candidate, &seen) in visited.iter().enumerate() {
+            if !seen && matrix.dist(current, candidate) < best_dist {
+                best_dist = matrix.dist(current, candidate);
+                best = candidate;
+            }
+        }
+        visited[best] = true;
+        tour.push(best);
+        current = best;
+    }
+    return tour;
+}
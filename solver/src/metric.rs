@@ -0,0 +1,137 @@
+use crate::math;
+use crate::shared::{Point, SimdF32};
+use std::simd::StdFloat;
+use std::simd::num::SimdFloat;
+
+// TSPLIB constants for GEO distances, deliberately the truncated values from the spec
+#[allow(clippy::approx_constant)]
+const GEO_PI: f64 = 3.141592;
+const GEO_RADIUS: f64 = 6378.388;
+
+/// The TSPLIB EDGE_WEIGHT_TYPE distance functions for coordinate instances.
+///
+/// `dist` is the continuous distance the construction and local searches work with,
+/// `tsplib_dist` applies the integer rounding the TSPLIB spec defines for reporting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Metric {
+    #[default]
+    Euc2d,
+    Ceil2d,
+    Att,
+    Geo,
+    Man2d,
+}
+
+impl Metric {
+    pub fn from_tsplib(name: &str) -> Option<Metric> {
+        match name {
+            "EUC_2D" => Some(Metric::Euc2d),
+            "CEIL_2D" => Some(Metric::Ceil2d),
+            "ATT" => Some(Metric::Att),
+            "GEO" => Some(Metric::Geo),
+            "MAN_2D" => Some(Metric::Man2d),
+            _ => None,
+        }
+    }
+
+    pub fn tsplib_name(&self) -> &'static str {
+        match self {
+            Metric::Euc2d => "EUC_2D",
+            Metric::Ceil2d => "CEIL_2D",
+            Metric::Att => "ATT",
+            Metric::Geo => "GEO",
+            Metric::Man2d => "MAN_2D",
+        }
+    }
+
+    /// Whether distances are straight lines in the coordinate plane (up to a constant factor),
+    /// which is what the geometric parts of the solver such as point to edge distance assume
+    pub fn is_planar(&self) -> bool {
+        matches!(self, Metric::Euc2d | Metric::Ceil2d | Metric::Att)
+    }
+
+    #[inline(always)]
+    pub fn dist(&self, a: Point, b: Point) -> f32 {
+        match self {
+            Metric::Euc2d | Metric::Ceil2d => math::calc_dist(a, b),
+            Metric::Att => {
+                let dx = a.x - b.x;
+                let dy = a.y - b.y;
+                (dx.mul_add(dx, dy * dy) / 10.0).sqrt()
+            }
+            Metric::Geo => geo_dist(a, b) as f32,
+            Metric::Man2d => (a.x - b.x).abs() + (a.y - b.y).abs(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn dist_simd(&self, a_x: SimdF32, a_y: SimdF32, b_x: SimdF32, b_y: SimdF32) -> SimdF32 {
+        match self {
+            Metric::Euc2d | Metric::Ceil2d => math::calc_dist_simd(a_x, a_y, b_x, b_y),
+            Metric::Att => {
+                let dx = a_x - b_x;
+                let dy = a_y - b_y;
+                (dx.mul_add(dx, dy * dy) / SimdF32::splat(10.0)).sqrt()
+            }
+            Metric::Geo => {
+                // No SIMD trigonometry in std, fall back to one lane at a time
+                let mut out = [0.0f32; 8];
+                for (lane, value) in out.iter_mut().enumerate() {
+                    *value = geo_dist(
+                        Point {
+                            x: a_x[lane],
+                            y: a_y[lane],
+                        },
+                        Point {
+                            x: b_x[lane],
+                            y: b_y[lane],
+                        },
+                    ) as f32;
+                }
+                SimdF32::from_array(out)
+            }
+            Metric::Man2d => (a_x - b_x).abs() + (a_y - b_y).abs(),
+        }
+    }
+
+    /// The distance between a and b rounded the way the TSPLIB spec prescribes for this type
+    pub fn tsplib_dist(&self, a: Point, b: Point) -> i64 {
+        let dx = a.x as f64 - b.x as f64;
+        let dy = a.y as f64 - b.y as f64;
+        match self {
+            Metric::Euc2d => nint((dx * dx + dy * dy).sqrt()),
+            Metric::Ceil2d => (dx * dx + dy * dy).sqrt().ceil() as i64,
+            Metric::Att => {
+                let r = ((dx * dx + dy * dy) / 10.0).sqrt();
+                let t = nint(r);
+                if (t as f64) < r { t + 1 } else { t }
+            }
+            // The spec truncates rather than rounds, after adding one
+            Metric::Geo => (geo_dist(a, b) + 1.0) as i64,
+            Metric::Man2d => nint(dx.abs() + dy.abs()),
+        }
+    }
+}
+
+fn nint(x: f64) -> i64 {
+    (x + 0.5) as i64
+}
+
+// Converts a TSPLIB DDD.MM coordinate to radians
+fn geo_radians(x: f32) -> f64 {
+    let x = x as f64;
+    let deg = x.trunc();
+    let min = x - deg;
+    GEO_PI * (deg + 5.0 * min / 3.0) / 180.0
+}
+
+// Great circle distance in kilometres as defined by TSPLIB, x is latitude and y longitude
+fn geo_dist(a: Point, b: Point) -> f64 {
+    let (lat_a, lon_a) = (geo_radians(a.x), geo_radians(a.y));
+    let (lat_b, lon_b) = (geo_radians(b.x), geo_radians(b.y));
+    let q1 = (lon_a - lon_b).cos();
+    let q2 = (lat_a - lat_b).cos();
+    let q3 = (lat_a + lat_b).cos();
+    let cos = (0.5 * ((1.0 + q1) * q2 - (1.0 - q1) * q3)).clamp(-1.0, 1.0);
+    GEO_RADIUS * cos.acos()
+}
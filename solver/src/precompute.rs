@@ -1,58 +1,160 @@
-use crate::shared::Point;
 use rustc_hash::FxHashMap as HashMap;
-pub struct SpatialGrid {
-    grid: HashMap<(i32, i32), Vec<Point>>,
+
+use crate::shared::Point;
+
+// Buckets cities by cell so the ones near an edge can be found without scanning them all.
+// Cities are indices into the points the grid was built from.
+//
+// The cells are stored CSR style, column by column: the cities of cell c live in
+// cities[cell_start[c]..cell_start[c] + cell_len[c]], so the whole grid is a few arrays the
+// size of the cities it was built with. Removing a city swaps it with the last live city of
+// its cell, which leaves its place free for restore_point.
+pub struct SpatialGrid<'a> {
+    cell_start: Vec<usize>,
+    cell_len: Vec<usize>,
+    cities: Vec<usize>,
+    // Where each city the grid was built with sits in cities, removed ones past their cell_len
+    slot: HashMap<usize, usize>,
+    // Number of cities still in the grid
+    live: usize,
+    points: &'a [Point],
+    cols: usize,
+    rows: usize,
     cell_size: f32,
     min_x: f32,
     min_y: f32,
 }
 
-impl SpatialGrid {
-    pub fn new(points: &[Point]) -> Self {
+/// What SpatialGrid::scan_edge_candidates saw. The same search with radius r gives the same
+/// candidates as long as inside <= r * r < outside and cells_to_check(r) <= cells, and
+/// removing a city from the grid can only change them when it is one of scanned
+pub struct EdgeScan {
+    pub candidates: Vec<usize>,
+    /// Every city the search looked at, lowest first
+    pub scanned: Vec<usize>,
+    /// The largest squared distance to its center of a looked at city within the radius
+    pub inside: f32,
+    /// The smallest squared distance to its center of a looked at city outside the radius
+    pub outside: f32,
+    /// The cells looked at in every direction around each center
+    pub cells: i32,
+}
+
+impl<'a> SpatialGrid<'a> {
+    // Builds a grid holding the given cities, sized to fit them
+    pub fn new(points: &'a [Point], cities: &[usize]) -> Self {
         // Calculate bounds
-        let min_x = points.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
-        let max_x = points.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
-        let min_y = points.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
-        let max_y = points.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+        let xs = || cities.iter().map(|&c| points[c].x);
+        let ys = || cities.iter().map(|&c| points[c].y);
+        let min_x = xs().fold(f32::INFINITY, f32::min);
+        let max_x = xs().fold(f32::NEG_INFINITY, f32::max);
+        let min_y = ys().fold(f32::INFINITY, f32::min);
+        let max_y = ys().fold(f32::NEG_INFINITY, f32::max);
 
         // Calculate average edge length for cell sizing
         let width = max_x - min_x;
         let height = max_y - min_y;
         let diagonal = (width * width + height * height).sqrt();
 
-        // Cell size should be roughly 1/sqrt(n) of the diagonal for good distribution
-        let cell_size = (diagonal / (points.len() as f32).sqrt()) * 0.5;
-
-        let mut grid =
-            HashMap::with_capacity_and_hasher(points.len() * points.len(), Default::default());
-
-        for &point in points {
-            let cell = Self::point_to_cell(point, cell_size, min_x, min_y);
-            grid.entry(cell).or_insert_with(Vec::new).push(point);
+        // Cell size should be roughly 1/sqrt(n) of the diagonal for good distribution,
+        // which makes at most about 4n cells
+        let mut cell_size = (diagonal / (cities.len() as f32).sqrt()) * 0.5;
+        if !cell_size.is_finite() || cell_size <= 0.0 {
+            // Fewer than two distinct places, any size puts them all in one cell
+            cell_size = 1.0;
         }
+        let (min_x, min_y) = if cities.is_empty() {
+            (0.0, 0.0)
+        } else {
+            (min_x, min_y)
+        };
+        let cols = (width.max(0.0) / cell_size) as usize + 1;
+        let rows = (height.max(0.0) / cell_size) as usize + 1;
 
-        SpatialGrid {
-            grid,
+        let mut grid = SpatialGrid {
+            cell_start: vec![0; cols * rows + 1],
+            cell_len: vec![0; cols * rows],
+            cities: vec![0; cities.len()],
+            slot: HashMap::default(),
+            live: cities.len(),
+            points,
+            cols,
+            rows,
             cell_size,
             min_x,
             min_y,
+        };
+
+        // Count the cities of each cell, turn the counts into offsets, then fill the cells
+        for &city in cities {
+            let cell = grid.cell_of(points[city]);
+            grid.cell_len[cell] += 1;
+        }
+        for cell in 0..cols * rows {
+            grid.cell_start[cell + 1] = grid.cell_start[cell] + grid.cell_len[cell];
         }
+        grid.cell_len.fill(0);
+        grid.slot.reserve(cities.len());
+        for &city in cities {
+            let cell = grid.cell_of(points[city]);
+            let at = grid.cell_start[cell] + grid.cell_len[cell];
+            grid.cities[at] = city;
+            grid.slot.insert(city, at);
+            grid.cell_len[cell] += 1;
+        }
+        grid
     }
 
     fn point_to_cell(point: Point, cell_size: f32, min_x: f32, min_y: f32) -> (i32, i32) {
         (
-            ((point.x - min_x) / cell_size) as i32,
-            ((point.y - min_y) / cell_size) as i32,
+            ((point.x - min_x) / cell_size).floor() as i32,
+            ((point.y - min_y) / cell_size).floor() as i32,
         )
     }
 
+    // The cell of a city in the grid, which always lies inside the bounds
+    fn cell_of(&self, point: Point) -> usize {
+        let (col, row) = Self::point_to_cell(point, self.cell_size, self.min_x, self.min_y);
+        let col = (col.max(0) as usize).min(self.cols - 1);
+        let row = (row.max(0) as usize).min(self.rows - 1);
+        col * self.rows + row
+    }
+
+    // The live cities of a cell
+    fn cell(&self, cell: usize) -> &[usize] {
+        let start = self.cell_start[cell];
+        &self.cities[start..start + self.cell_len[cell]]
+    }
+
     pub fn query_edge_candidates(
         &self,
         edge_start: Point,
         edge_end: Point,
         max_distance: f32,
-    ) -> Vec<Point> {
-        let mut candidates = Vec::new();
+    ) -> Vec<usize> {
+        return self
+            .scan_edge_candidates(edge_start, edge_end, max_distance, usize::MAX)
+            .candidates;
+    }
+
+    /// The first limit cities query_edge_candidates would return, without looking any
+    /// further, together with what it takes for the answer to stay the same
+    pub fn scan_edge_candidates(
+        &self,
+        edge_start: Point,
+        edge_end: Point,
+        max_distance: f32,
+        limit: usize,
+    ) -> EdgeScan {
+        let cells = self.cells_to_check(max_distance);
+        let radius_sq = max_distance * max_distance;
+        let mut scan = EdgeScan {
+            candidates: Vec::new(),
+            scanned: Vec::new(),
+            inside: f32::NEG_INFINITY,
+            outside: f32::INFINITY,
+            cells,
+        };
         let points_to_test = [
             edge_start,
             edge_end,
@@ -62,79 +164,211 @@ impl SpatialGrid {
             },
         ];
         for point in points_to_test.iter() {
-            let nearby = self.query_radius(point.clone(), max_distance);
-            for candidate in nearby {
-                if !candidates.contains(&candidate) {
-                    candidates.push(candidate);
+            self.visit_cells(*point, cells, |candidate, dist_sq| {
+                scan.scanned.push(candidate);
+                if dist_sq > radius_sq {
+                    scan.outside = scan.outside.min(dist_sq);
+                    return true;
                 }
+                scan.inside = scan.inside.max(dist_sq);
+                if !scan.candidates.contains(&candidate) {
+                    scan.candidates.push(candidate);
+                }
+                scan.candidates.len() < limit
+            });
+            if scan.candidates.len() >= limit {
+                break;
             }
         }
-        return candidates;
+        scan.scanned.sort_unstable();
+        scan.scanned.dedup();
+        return scan;
     }
 
-    pub fn query_radius(&self, center: Point, radius: f32) -> Vec<Point> {
-        let cells_to_check = ((radius / self.cell_size).ceil() as i32).max(1);
-        let center_cell = Self::point_to_cell(center, self.cell_size, self.min_x, self.min_y);
-
+    pub fn query_radius(&self, center: Point, radius: f32) -> Vec<usize> {
         let mut candidates = Vec::new();
         let radius_sq = radius * radius;
+        self.visit_cells(center, self.cells_to_check(radius), |city, dist_sq| {
+            if dist_sq <= radius_sq {
+                candidates.push(city);
+            }
+            true
+        });
+        candidates
+    }
 
-        for dx in -cells_to_check..=cells_to_check {
-            for dy in -cells_to_check..=cells_to_check {
-                let cell = (center_cell.0 + dx, center_cell.1 + dy);
-                if let Some(points) = self.grid.get(&cell) {
-                    for &point in points {
-                        let dist_sq = (point.x - center.x).powi(2) + (point.y - center.y).powi(2);
-                        if dist_sq <= radius_sq {
-                            candidates.push(point);
-                        }
+    /// How many cells a search within radius looks at in every direction from the cell of its
+    /// center. A city further out than that is always further away than radius
+    pub fn cells_to_check(&self, radius: f32) -> i32 {
+        ((radius / self.cell_size).ceil() as i32).max(1)
+    }
+
+    // Calls visit with every city up to cells_to_check cells from center and its squared
+    // distance to center, cell by cell, until it returns false
+    fn visit_cells(
+        &self,
+        center: Point,
+        cells_to_check: i32,
+        mut visit: impl FnMut(usize, f32) -> bool,
+    ) {
+        let center_cell = Self::point_to_cell(center, self.cell_size, self.min_x, self.min_y);
+
+        // Only the cells that exist, a big radius would otherwise walk a mostly empty square
+        let cols = center_cell.0.saturating_sub(cells_to_check).max(0)
+            ..=center_cell
+                .0
+                .saturating_add(cells_to_check)
+                .min(self.cols as i32 - 1);
+        let rows = center_cell.1.saturating_sub(cells_to_check).max(0)
+            ..=center_cell
+                .1
+                .saturating_add(cells_to_check)
+                .min(self.rows as i32 - 1);
+
+        for col in cols {
+            for row in rows.clone() {
+                for &city in self.cell(col as usize * self.rows + row as usize) {
+                    let point = self.points[city];
+                    let dist_sq = (point.x - center.x).powi(2) + (point.y - center.y).powi(2);
+                    if !visit(city, dist_sq) {
+                        return;
                     }
                 }
             }
         }
-        candidates
     }
 
-    pub fn remove_point(&mut self, point: Point) {
-        let cell = Self::point_to_cell(point, self.cell_size, self.min_x, self.min_y);
-        if let Some(points) = self.grid.get_mut(&cell) {
-            points.retain(|&p| p != point);
-            // Remove empty cells to keep the grid clean
-            if points.is_empty() {
-                self.grid.remove(&cell);
-            }
+    pub fn remove_point(&mut self, city: usize) {
+        if !self.contains_point(city) {
+            return;
         }
+        let at = self.slot[&city];
+        let cell = self.cell_of(self.points[city]);
+        let last = self.cell_start[cell] + self.cell_len[cell] - 1;
+        self.swap(at, last);
+        self.cell_len[cell] -= 1;
+        self.live -= 1;
     }
 
-    pub fn contains_point(&self, point: Point) -> bool {
-        let cell = Self::point_to_cell(point, self.cell_size, self.min_x, self.min_y);
-        if let Some(points) = self.grid.get(&cell) {
-            points.contains(&point)
-        } else {
-            return false;
+    /// Puts a removed city back. Only a city the grid was built with can come back, there is
+    /// no room in its cell for any other
+    pub fn restore_point(&mut self, city: usize) {
+        if self.contains_point(city) || !self.slot.contains_key(&city) {
+            return;
         }
+        let at = self.slot[&city];
+        let cell = self.cell_of(self.points[city]);
+        let first_free = self.cell_start[cell] + self.cell_len[cell];
+        self.swap(at, first_free);
+        self.cell_len[cell] += 1;
+        self.live += 1;
+    }
+
+    // Swaps two places in cities, keeping slot in step
+    fn swap(&mut self, i: usize, j: usize) {
+        let (a, b) = (self.cities[i], self.cities[j]);
+        self.cities.swap(i, j);
+        self.slot.insert(a, j);
+        self.slot.insert(b, i);
+    }
+
+    pub fn contains_point(&self, city: usize) -> bool {
+        let Some(&at) = self.slot.get(&city) else {
+            return false;
+        };
+        let cell = self.cell_of(self.points[city]);
+        at < self.cell_start[cell] + self.cell_len[cell]
+    }
+
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    /// The cities still in the grid, lowest first
+    pub fn cities(&self) -> Vec<usize> {
+        let mut cities: Vec<usize> = (0..self.cell_len.len())
+            .flat_map(|cell| self.cell(cell).iter().copied())
+            .collect();
+        cities.sort_unstable();
+        cities
+    }
+
+    // The city still in the grid closest to p in a straight line, ties going to the lowest
+    // city so the answer never depends on the order inside a cell
+    pub fn nearest(&self, p: Point) -> Option<usize> {
+        return self.k_nearest(p, 1).first().copied();
     }
-}
 
-// Calculate reasonable search radius based on hull size
-pub fn calculate_search_radius(hull: &[Point]) -> f32 {
-    if hull.len() < 3 {
-        return 100.0; // Default fallback
+    // The same as nearest, but only looking as far as radius from p
+    pub fn nearest_within(&self, p: Point, radius: f32) -> Option<usize> {
+        return self.k_nearest_within(p, 1, radius).first().copied();
     }
 
-    // Calculate average edge length in current hull
-    let mut total_length = 0.0;
-    for i in 0..hull.len() {
-        let current = hull[i];
-        let next = hull[(i + 1) % hull.len()];
-        let dx = next.x - current.x;
-        let dy = next.y - current.y;
-        total_length += (dx * dx + dy * dy).sqrt();
+    // The k cities still in the grid closest to p in a straight line, closest first.
+    // Looks at the cells ring by ring around p and stops once a ring is further away than
+    // the k-th best so far, so only the neighbourhood of p is visited
+    pub fn k_nearest(&self, p: Point, k: usize) -> Vec<usize> {
+        return self.k_nearest_within(p, k, f32::INFINITY);
     }
 
-    let avg_edge_length = total_length / hull.len() as f32;
+    // k_nearest leaving out every city further than radius from p
+    fn k_nearest_within(&self, p: Point, k: usize, radius: f32) -> Vec<usize> {
+        let k = k.min(self.live);
+        let radius_sq = radius * radius;
+        // (squared distance, city) of the best so far, ascending
+        let mut best: Vec<(f32, usize)> = Vec::with_capacity(k + 1);
+        if k == 0 {
+            return vec![];
+        }
+
+        let (col, row) = Self::point_to_cell(p, self.cell_size, self.min_x, self.min_y);
+        let (col, row) = (col as i64, row as i64);
+        let (cols, rows) = (self.cols as i64, self.rows as i64);
+        // Beyond this ring there are no cells left
+        let last_ring = col.max(cols - 1 - col).max(row).max(rows - 1 - row);
 
-    // Search radius should be proportional to average edge length
-    // This prevents searching too far from relevant edges
-    return avg_edge_length * 2.0;
+        // Rings that miss the grid entirely hold nothing, p may lie well outside it
+        let mut ring = 0i64
+            .max(-col)
+            .max(col - (cols - 1))
+            .max(-row)
+            .max(row - (rows - 1));
+        while ring <= last_ring {
+            for c in (col - ring).max(0)..=(col + ring).min(cols - 1) {
+                // Only the border of the ring, the inside was searched already
+                let on_side = c == col - ring || c == col + ring;
+                let step = if on_side {
+                    1
+                } else {
+                    (2 * ring).max(1) as usize
+                };
+                for r in ((row - ring)..=(row + ring)).step_by(step) {
+                    if r < 0 || r >= rows {
+                        continue;
+                    }
+                    for &city in self.cell(c as usize * self.rows + r as usize) {
+                        let q = self.points[city];
+                        let d = (q.x - p.x).powi(2) + (q.y - p.y).powi(2);
+                        if d > radius_sq || (best.len() == k && (d, city) >= best[k - 1]) {
+                            continue;
+                        }
+                        let at = best.partition_point(|&entry| entry < (d, city));
+                        best.insert(at, (d, city));
+                        best.truncate(k);
+                    }
+                }
+            }
+            // Every cell of the next ring is at least ring whole cells away from p
+            let reach = ring as f32 * self.cell_size;
+            if reach > radius || (best.len() == k && best[k - 1].0 <= reach * reach) {
+                break;
+            }
+            ring += 1;
+        }
+        return best.into_iter().map(|(_, city)| city).collect();
+    }
 }
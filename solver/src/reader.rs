@@ -1,113 +1,516 @@
+use crate::error::{ParseError, ParseErrorKind, ReadError};
+use crate::instance::Instance;
+use crate::matrix::{DistanceMatrix, WeightFormat};
+use crate::metric::Metric;
 use crate::shared;
-use std::env;
+use rustc_hash::FxHashMap as HashMap;
 use std::fs;
 use std::fs::File;
-use std::io::Write;
-pub fn read_file() -> String {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <filename>", args[0]);
-        std::process::exit(1);
-    }
-    if &args[1] == "help" || &args[1] == "--help" || &args[1] == "-help" {
-        println!("To use run ./tsp.exe <PATH TO .tsp FILE>");
-        std::process::exit(1);
+use std::io::{self, Read, Write};
+
+// Reads a problem from source, which is one of
+//   a path to a TSPLIB file
+//   - to read a TSPLIB file or a plain point list from stdin
+//   [x1, y1, x2, y2, ...] to give the points inline
+pub fn read_source(source: &str) -> Result<Instance, ReadError> {
+    let instance = if source.starts_with('[') {
+        parse_point_array(source)?
+    } else if source == "-" {
+        parse_text(&read_text(source)?)?
+    } else {
+        parse_file(&read_text(source)?)?
+    };
+    if instance.is_empty() {
+        return Err(ParseError::new(0, "input", "", ParseErrorKind::NoCities).into());
     }
-    let filename = &args[1];
-    return fs::read_to_string(filename).unwrap();
+    return Ok(instance);
+}
+
+// Reads the node numbers of a tour from a file, or from stdin when path is "-"
+pub fn read_tour(path: &str) -> Result<Vec<usize>, ReadError> {
+    return Ok(parse_tour(&read_text(path)?)?);
 }
 
-pub fn parse_num(input: &String) -> f32 {
-    // Rust handles scientific notation parsing directly, so:
-    return input.parse::<f32>().unwrap();
+fn read_text(source: &str) -> Result<String, ReadError> {
+    let mut text = String::new();
+    let result = if source == "-" {
+        io::stdin().read_to_string(&mut text).map(|_| ())
+    } else {
+        fs::read_to_string(source).map(|read| text = read)
+    };
+    return match result {
+        Ok(()) => Ok(text),
+        Err(error) => Err(ReadError::Io {
+            source: if source == "-" {
+                "stdin".to_string()
+            } else {
+                source.to_string()
+            },
+            error,
+        }),
+    };
 }
-pub fn should_log() -> bool {
-    let args: Vec<String> = env::args().collect();
-    let sl = String::from("--no-log");
-    if args.contains(&sl) {
-        return true;
+
+// Parses text that is either a TSPLIB file, a [x1, y1, ...] array or one point per line
+pub fn parse_text(text: &str) -> Result<Instance, ParseError> {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('[') {
+        return parse_point_array(trimmed);
+    }
+    if text.contains("_SECTION") {
+        return parse_file(text);
     }
-    return false;
+    return parse_point_list(text);
 }
-pub fn should_or_opt() -> bool {
-    let args: Vec<String> = env::args().collect();
-    let soo = String::from("--no-or-opt");
-    if args.contains(&soo) {
-        return true;
+
+// Parses [x1, y1, x2, y2, ...] into the points (x1, y1), (x2, y2), ...
+pub fn parse_point_array(text: &str) -> Result<Instance, ParseError> {
+    let section = "point array";
+    let numbers = text
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|token| parse_num(token, 0, section))
+        .collect::<Result<Vec<f32>, ParseError>>()?;
+    if !numbers.len().is_multiple_of(2) {
+        return Err(ParseError::new(
+            0,
+            section,
+            "",
+            ParseErrorKind::OddPointCount {
+                found: numbers.len(),
+            },
+        ));
     }
-    return false;
+    let points = numbers
+        .chunks(2)
+        .map(|xy| shared::Point { x: xy[0], y: xy[1] })
+        .collect();
+    return Ok(Instance::from_points("points", points));
 }
-pub fn should_edge_swap() -> bool {
-    let args: Vec<String> = env::args().collect();
-    let ses = String::from("--no-edge-switch");
-    if args.contains(&ses) {
-        return true;
+
+// Parses one point per line as "x y" or "id x y", separated by spaces or commas
+pub fn parse_point_list(text: &str) -> Result<Instance, ParseError> {
+    let section = "point list";
+    let mut instance = Instance::from_points("points", vec![]);
+    let mut id_lines = HashMap::default();
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let split: Vec<&str> = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .collect();
+        match split.len() {
+            0 => {}
+            1 => {
+                return Err(ParseError::new(
+                    line_no,
+                    section,
+                    line.trim(),
+                    ParseErrorKind::MissingColumns { expected: 2 },
+                ));
+            }
+            2 => {
+                // Numbered by position, which an "id x y" line may have taken already
+                let id = instance.points.len() + 1;
+                check_new_id(&mut id_lines, line.trim(), id, line_no, section)?;
+                instance.node_ids.push(id);
+                instance.points.push(shared::Point {
+                    x: parse_num(split[0], line_no, section)?,
+                    y: parse_num(split[1], line_no, section)?,
+                });
+            }
+            _ => {
+                let id = parse_id(split[0], line_no, section)?;
+                check_new_id(&mut id_lines, split[0], id, line_no, section)?;
+                instance.node_ids.push(id);
+                instance.points.push(shared::Point {
+                    x: parse_num(split[1], line_no, section)?,
+                    y: parse_num(split[2], line_no, section)?,
+                });
+            }
+        }
     }
-    return false;
+    return Ok(instance);
+}
+
+pub fn parse_num(input: &str, line: usize, section: &'static str) -> Result<f32, ParseError> {
+    // Rust handles scientific notation parsing directly, but it also takes nan and inf, and
+    // rounds numbers past the f32 range to inf. None of those is a place or a distance
+    return match input.parse::<f32>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(ParseError::new(
+            line,
+            section,
+            input,
+            ParseErrorKind::ExpectedNumber,
+        )),
+    };
 }
-pub fn should_relp() -> bool {
-    let args: Vec<String> = env::args().collect();
-    let srelp = String::from("--no-relp");
-    if args.contains(&srelp) {
-        return true;
+
+// Remembers the line each node number was given on, a number given twice would leave the
+// solver unable to tell the two cities apart in the tour it writes
+fn check_new_id(
+    id_lines: &mut HashMap<usize, usize>,
+    token: &str,
+    id: usize,
+    line: usize,
+    section: &'static str,
+) -> Result<(), ParseError> {
+    if let Some(&first_line) = id_lines.get(&id) {
+        return Err(ParseError::new(
+            line,
+            section,
+            token,
+            ParseErrorKind::RepeatedNodeId { first_line },
+        ));
     }
-    return false;
+    id_lines.insert(id, line);
+    return Ok(());
+}
+
+fn parse_id(input: &str, line: usize, section: &'static str) -> Result<usize, ParseError> {
+    return input
+        .parse::<usize>()
+        .map_err(|_| ParseError::new(line, section, input, ParseErrorKind::ExpectedNodeId));
 }
-pub fn no_post() -> bool {
-    let args: Vec<String> = env::args().collect();
-    let snp = String::from("--no-post");
-    if args.contains(&snp) {
-        return true;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A TSPLIB problem file with the cities listed in tour order
+    Tsp,
+    /// A TSPLIB tour file (TYPE : TOUR) listing node numbers in TOUR_SECTION
+    Tour,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Header,
+    NodeCoords,
+    EdgeWeights,
+    Tour,
+    // Sections the solver has no use for, such as DISPLAY_DATA_SECTION
+    Ignored,
+}
+
+impl Section {
+    // The name errors report for lines of this section
+    fn name(&self) -> &'static str {
+        match self {
+            Section::Header => "header",
+            Section::NodeCoords => "NODE_COORD_SECTION",
+            Section::EdgeWeights => "EDGE_WEIGHT_SECTION",
+            Section::Tour => "TOUR_SECTION",
+            Section::Ignored => "ignored section",
+        }
     }
-    return false;
 }
 
-pub fn parse_file(file: &String) -> Vec<shared::Point> {
-    let parts: Vec<&str> = file.split("NODE_COORD_SECTION").collect();
-    if parts.len() < 2 {
-        return vec![];
+fn section_from_keyword(line: &str) -> Option<Section> {
+    match line.trim_end_matches(':').trim() {
+        "NODE_COORD_SECTION" => Some(Section::NodeCoords),
+        "EDGE_WEIGHT_SECTION" => Some(Section::EdgeWeights),
+        keyword if keyword.ends_with("_SECTION") => Some(Section::Ignored),
+        _ => None,
     }
-    let file_contents_2 = parts[1];
-    let file_contents_3 = file_contents_2.split("EOF").next().unwrap_or("");
+}
 
-    let lines: Vec<&str> = file_contents_3.lines().collect();
-    let mut to_return = Vec::new();
+pub fn parse_file(file: &str) -> Result<Instance, ParseError> {
+    let mut instance = Instance::default();
+    let mut section = Section::Header;
+    let mut dimension = 0;
+    // Where DIMENSION was given, and the first city beyond it if the section has more
+    let mut dimension_line = 0;
+    let mut extra_row = None;
+    let mut explicit = false;
+    let mut weight_format = None;
+    let mut weights: Vec<f32> = Vec::new();
+    // The index of the first weight of every EDGE_WEIGHT_SECTION line, with its line number
+    let mut weight_lines: Vec<(usize, usize)> = Vec::new();
+    let mut id_lines = HashMap::default();
 
-    for line in lines {
-        let split: Vec<&str> = line.split_whitespace().collect();
-        if split.len() >= 3 {
-            to_return.push(shared::Point {
-                x: parse_num(&split[1].to_string()),
-                y: parse_num(&split[2].to_string()),
-            });
+    for (index, line) in file.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
         }
+        if line == "EOF" {
+            break;
+        }
+        if let Some(next) = section_from_keyword(line) {
+            section = next;
+            continue;
+        }
+        // Data lines start with a number, anything else is another header entry. Words that
+        // parse as a float, like nan or inf, stay data so parse_num can reject them
+        if section != Section::Header
+            && line.starts_with(|c: char| c.is_ascii_alphabetic())
+            && line
+                .split_whitespace()
+                .next()
+                .unwrap()
+                .parse::<f32>()
+                .is_err()
+        {
+            section = Section::Header;
+        }
+
+        match section {
+            Section::Header => {
+                let Some((key, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let value = value.trim();
+                let unsupported = |header| {
+                    ParseError::new(
+                        line_no,
+                        section.name(),
+                        value,
+                        ParseErrorKind::Unsupported { key: header },
+                    )
+                };
+                match key.trim() {
+                    "NAME" => instance.name = value.to_string(),
+                    "TYPE" if value == "ATSP" => return Err(unsupported("TYPE")),
+                    "DIMENSION" => {
+                        dimension = parse_id(value, line_no, section.name())?;
+                        dimension_line = line_no;
+                    }
+                    "EDGE_WEIGHT_TYPE" if value == "EXPLICIT" => explicit = true,
+                    "EDGE_WEIGHT_TYPE" => {
+                        instance.metric = Metric::from_tsplib(value)
+                            .ok_or_else(|| unsupported("EDGE_WEIGHT_TYPE"))?
+                    }
+                    "EDGE_WEIGHT_FORMAT" => {
+                        weight_format = Some(
+                            WeightFormat::from_tsplib(value)
+                                .ok_or_else(|| unsupported("EDGE_WEIGHT_FORMAT"))?,
+                        )
+                    }
+                    _ => {}
+                }
+            }
+            Section::NodeCoords => {
+                let split: Vec<&str> = line.split_whitespace().collect();
+                if split.len() < 3 {
+                    return Err(ParseError::new(
+                        line_no,
+                        section.name(),
+                        line,
+                        ParseErrorKind::MissingColumns { expected: 3 },
+                    ));
+                }
+                let id = parse_id(split[0], line_no, section.name())?;
+                check_new_id(&mut id_lines, split[0], id, line_no, section.name())?;
+                if instance.points.len() == dimension && extra_row.is_none() {
+                    extra_row = Some((line_no, line));
+                }
+                instance.node_ids.push(id);
+                instance.points.push(shared::Point {
+                    x: parse_num(split[1], line_no, section.name())?,
+                    y: parse_num(split[2], line_no, section.name())?,
+                });
+            }
+            Section::EdgeWeights => {
+                weight_lines.push((weights.len(), line_no));
+                for token in line.split_whitespace() {
+                    weights.push(parse_num(token, line_no, section.name())?);
+                }
+            }
+            Section::Tour | Section::Ignored => {}
+        }
+    }
+
+    // A count that disagrees with DIMENSION means a truncated or badly joined file
+    if !explicit && dimension_line > 0 && instance.points.len() != dimension {
+        let (line, section, token) = match extra_row {
+            Some((line, row)) => (line, Section::NodeCoords.name(), row.to_string()),
+            None => (
+                dimension_line,
+                Section::Header.name(),
+                dimension.to_string(),
+            ),
+        };
+        return Err(ParseError::new(
+            line,
+            section,
+            &token,
+            ParseErrorKind::DimensionMismatch {
+                dimension,
+                found: instance.points.len(),
+            },
+        ));
     }
 
-    return to_return;
+    if explicit {
+        let Some(format) = weight_format else {
+            return Err(ParseError::new(
+                0,
+                "header",
+                "",
+                ParseErrorKind::MissingWeightFormat,
+            ));
+        };
+        if weights.len() != format.value_count(dimension) {
+            return Err(ParseError::new(
+                0,
+                Section::EdgeWeights.name(),
+                &format!("a {:?} matrix of dimension {}", format, dimension),
+                ParseErrorKind::WrongValueCount {
+                    expected: format.value_count(dimension),
+                    found: weights.len(),
+                },
+            ));
+        }
+        if format == WeightFormat::FullMatrix {
+            check_symmetric(&weights, dimension, &weight_lines)?;
+        }
+        // Explicit instances have no coordinates to carry node numbers, TSPLIB numbers them 1..n
+        instance.node_ids = (1..=dimension).collect();
+        instance.points.clear();
+        instance.matrix = Some(DistanceMatrix::from_tsplib(format, dimension, &weights));
+    }
+
+    return Ok(instance);
+}
+
+// The solver only handles symmetric matrices, so a FULL_MATRIX weight has to equal its mirror.
+// The error names the line of whichever of the two came second
+fn check_symmetric(
+    weights: &[f32],
+    n: usize,
+    weight_lines: &[(usize, usize)],
+) -> Result<(), ParseError> {
+    for i in 0..n {
+        for j in 0..i {
+            let index = i * n + j;
+            if weights[index] != weights[j * n + i] {
+                // Row i comes after row j, so the line holding index is the last one starting
+                // at or before it
+                let at = weight_lines.partition_point(|&(first, _)| first <= index);
+                let line = weight_lines[at - 1].1;
+                return Err(ParseError::new(
+                    line,
+                    Section::EdgeWeights.name(),
+                    &weights[index].to_string(),
+                    ParseErrorKind::AsymmetricWeight {
+                        row: i + 1,
+                        col: j + 1,
+                    },
+                ));
+            }
+        }
+    }
+    return Ok(());
 }
 
-pub fn vec_diff(a: &[shared::Point], b: &[shared::Point]) -> Vec<shared::Point> {
-    return a.iter().filter(|item| !b.contains(item)).cloned().collect();
+// Reads the node numbers of a tour, in order, from either a TSPLIB tour file (TOUR_SECTION,
+// terminated by -1) or a TSP file whose NODE_COORD_SECTION lists the cities in tour order
+pub fn parse_tour(text: &str) -> Result<Vec<usize>, ParseError> {
+    let mut tour = Vec::new();
+    let mut section = Section::Header;
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "EOF" {
+            break;
+        }
+        match line.trim_end_matches(':').trim() {
+            "TOUR_SECTION" => section = Section::Tour,
+            "NODE_COORD_SECTION" => section = Section::NodeCoords,
+            _ if line.starts_with(|c: char| c.is_ascii_alphabetic()) => section = Section::Header,
+            _ if section == Section::Tour => {
+                for id in line.split_whitespace() {
+                    if id == "-1" {
+                        return Ok(tour);
+                    }
+                    tour.push(parse_id(id, line_no, section.name())?);
+                }
+            }
+            _ if section == Section::NodeCoords => {
+                if let Some(id) = line.split_whitespace().next() {
+                    tour.push(parse_id(id, line_no, section.name())?);
+                }
+            }
+            _ => {}
+        }
+    }
+    return Ok(tour);
 }
 
-pub fn write_to_tsp_file(tour: &Vec<shared::Point>, path: &str) {
+// Writes the cities of instance in tour order, keeping their original node numbers
+// Only possible for instances with coordinates
+pub fn write_to_tsp_file(instance: &Instance, tour: &[usize], path: &str) -> io::Result<()> {
     let mut to_write = format!(
         "NAME : SOLVED
 COMMENT : Solved with tsp_solver (Copyright Chase Yalon)
 TYPE : TSP
 DIMENSION: {}
-EDGE_WEIGHT_TYPE : EUC_2D
+EDGE_WEIGHT_TYPE : {}
 NODE_COORD_SECTION
 ",
+        tour.len(),
+        instance.metric.tsplib_name()
+    );
+
+    for &i in tour {
+        let point = instance.points[i];
+        to_write += &format!("{}   {}   {}\n", instance.node_ids[i], point.x, point.y);
+    }
+
+    return write_string(&to_write, path);
+}
+
+// Writes a TSPLIB tour file that LKH, Concorde and other TSPLIB tools can read back
+pub fn write_to_tour_file(instance: &Instance, tour: &[usize], path: &str) -> io::Result<()> {
+    let mut to_write = format!(
+        "NAME : {}.tour
+COMMENT : Solved with tsp_solver (Copyright Chase Yalon)
+TYPE : TOUR
+DIMENSION : {}
+TOUR_SECTION
+",
+        if instance.name.is_empty() {
+            "SOLVED"
+        } else {
+            &instance.name
+        },
         tour.len()
     );
 
-    for (i, point) in tour.iter().enumerate() {
-        // TSP files are usually 1-based indexing for nodes
-        to_write += &format!("{}   {}   {}\n", i + 1, point.x, point.y);
+    for id in instance.tour_ids(tour) {
+        to_write += &format!("{}\n", id);
     }
+    to_write += "-1\nEOF\n";
 
-    let mut file = File::create(path).expect("Failed to create file");
-    file.write_all(to_write.as_bytes())
-        .expect("Failed to write to file");
+    return write_string(&to_write, path);
+}
+
+pub fn write_output(
+    instance: &Instance,
+    tour: &[usize],
+    path: &str,
+    format: OutputFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Tsp => write_to_tsp_file(instance, tour, path),
+        OutputFormat::Tour => write_to_tour_file(instance, tour, path),
+    }
+}
+
+// Writes to the file at path, or to stdout when path is "-"
+fn write_string(to_write: &str, path: &str) -> io::Result<()> {
+    if path == "-" {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(to_write.as_bytes())?;
+        return stdout.flush();
+    }
+    let mut file = File::create(path)?;
+    return file.write_all(to_write.as_bytes());
 }
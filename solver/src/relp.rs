@@ -1,24 +1,33 @@
 //Reluctation points
 
-use crate::shared;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
+
+use crate::criterion::InsertionCriterion;
+use crate::metric::Metric;
+use crate::shared::{Point, SimdF32};
+use crate::tour::Tour;
+
 #[derive(Debug, Clone, Copy)]
 pub struct InsertPointResult {
+    // Score of the insertion under the criterion it was made with, the LDA by default
     pub lda: f32,
-    pub best_a: shared::Point,
-    pub best_c: shared::Point,
+    // Cities, best_c goes into the tour right after best_a
+    pub best_a: usize,
+    pub best_c: usize,
 }
 
 #[derive(Debug, Clone, Copy)]
 struct LdaEntry {
     lda: f32,
-    point: shared::Point, // inserted point
+    city: usize, // inserted city
 }
 
 impl PartialEq for LdaEntry {
     fn eq(&self, other: &Self) -> bool {
-        self.lda == other.lda
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -26,48 +35,83 @@ impl Eq for LdaEntry {}
 
 impl PartialOrd for LdaEntry {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.lda.partial_cmp(&other.lda)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for LdaEntry {
+    // By score and then by city, so ties always keep the same cities and a NaN cannot panic
     fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap()
+        self.lda
+            .total_cmp(&other.lda)
+            .then(self.city.cmp(&other.city))
     }
 }
 
-pub fn find_lowest_lda_points(insert_log: &[InsertPointResult], k: usize) -> Vec<shared::Point> {
+pub fn find_lowest_lda_points(insert_log: &[InsertPointResult], k: usize) -> Vec<usize> {
     let mut heap = BinaryHeap::with_capacity(k);
 
     for result in insert_log {
         let entry = LdaEntry {
             lda: result.lda,
-            point: result.best_c,
+            city: result.best_c,
         };
 
         if heap.len() < k {
             heap.push(entry);
-        } else if let Some(&top) = heap.peek() {
-            if entry.lda < top.lda {
-                heap.pop();
-                heap.push(entry);
-            }
+        } else if let Some(&top) = heap.peek()
+            && entry < top
+        {
+            heap.pop();
+            heap.push(entry);
         }
     }
 
-    // Convert heap into sorted vec of Points (lowest to highest LDA)
+    // Convert heap into sorted vec of cities (lowest to highest LDA)
     heap.into_sorted_vec()
         .into_iter()
-        .map(|entry| entry.point)
+        .map(|entry| entry.city)
         .collect()
 }
 
-pub fn remove_points_from_hull(hull: &mut Vec<shared::Point>, to_remove: &[shared::Point]) {
-    const EPS: f32 = 1e-5;
-
-    fn eq(a: shared::Point, b: shared::Point) -> bool {
-        (a.x - b.x).abs() < EPS && (a.y - b.y).abs() < EPS
+/// The insertion log of a tour that was not built by insertion: every city scored by criterion
+/// as if it had gone in between its two neighbours in the tour, eight cities at a time
+pub fn score_tour(
+    tour: &[usize],
+    points: &[Point],
+    metric: Metric,
+    criterion: &dyn InsertionCriterion,
+) -> Vec<InsertPointResult> {
+    let n = tour.len();
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut log = Vec::with_capacity(n);
+    for first in (0..n).step_by(8) {
+        let len = 8.min(n - first);
+        // x and y of a, b and c in every lane
+        let mut lanes = [[0.0f32; 8]; 6];
+        for lane in 0..len {
+            let i = first + lane;
+            let a = points[tour[(i + n - 1) % n]];
+            let b = points[tour[(i + 1) % n]];
+            let c = points[tour[i]];
+            for (values, value) in lanes.iter_mut().zip([a.x, a.y, b.x, b.y, c.x, c.y]) {
+                values[lane] = value;
+            }
+        }
+        let [a_x, a_y, b_x, b_y, c_x, c_y] = lanes.map(SimdF32::from_array);
+        let scores = criterion.score(a_x, a_y, b_x, b_y, c_x, c_y, &mut rng, 0.0, metric);
+        for lane in 0..len {
+            let i = first + lane;
+            log.push(InsertPointResult {
+                lda: scores[lane],
+                best_a: tour[(i + n - 1) % n],
+                best_c: tour[i],
+            });
+        }
     }
+    return log;
+}
 
-    hull.retain(|p| !to_remove.iter().any(|r| eq(*p, *r)));
+pub fn remove_points_from_hull(hull: &mut Tour, to_remove: &[usize]) {
+    hull.remove_all(to_remove);
 }
@@ -26,3 +26,12 @@ impl Hash for Point {
 impl Eq for Point {}
 
 pub type SimdF32 = Simd<f32, 8>;
+
+// SplitMix64 finalizer, derives independent seeds for each stream (phase, round, edge) of a run
+// so that results never depend on which thread happens to draw from an RNG first
+pub fn mix_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed ^ stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,592 @@
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap as HashMap;
+use std::time::{Duration, Instant};
+
+use crate::candidates::{CandidateKind, Candidates};
+use crate::constructor::ConstructorKind;
+use crate::criterion::CriterionKind;
+use crate::edges;
+use crate::ils;
+use crate::insertion::insert_all;
+use crate::instance::Instance;
+use crate::lin_kernighan;
+use crate::math;
+use crate::matrix::{self, DistanceMatrix};
+use crate::metric::Metric;
+use crate::or_opt;
+use crate::precompute::SpatialGrid;
+use crate::relp::{self, find_lowest_lda_points, remove_points_from_hull};
+use crate::shared;
+use crate::three_opt;
+use crate::tour::{ArrayTour, Tour};
+use crate::two_opt;
+
+/// Which phases of the solver to run and whether to report progress.
+#[derive(Debug, Clone, Copy)]
+pub struct SolverConfig {
+    /// Show a progress bar while the tour is being constructed
+    pub log: bool,
+    /// Run every post-processing phase below; when false only the construction runs
+    pub post: bool,
+    /// Uncross crossing edges after construction
+    pub edge_swap: bool,
+    /// Run a full 2-opt over the candidate neighbour lists in place of crossing elimination
+    pub two_opt: bool,
+    /// Run or-opt segment moves after construction
+    pub or_opt: bool,
+    /// Run a sequential 3-opt over the candidate neighbour lists after or-opt
+    pub three_opt: bool,
+    /// Remove and reinsert the points with the lowest insertion LDA ("reluctant points"),
+    /// after 3-opt
+    pub relp: bool,
+    /// Run relp on the construction's tour, before any of the local searches, instead
+    pub relp_first: bool,
+    /// Finish with a Lin-Kernighan style search over the candidate neighbour lists
+    pub lin_kernighan: bool,
+    /// Stop the Lin-Kernighan search after this long, it runs to a local optimum without one
+    pub lk_time: Option<Duration>,
+    /// Keep kicking and repairing the tour until the whole solve has taken this long. Starts
+    /// that have to wait for a thread get an equal share of it each
+    pub time_limit: Option<Duration>,
+    /// Seed for every random choice, the same seed and input always give the same tour
+    pub seed: u64,
+    /// How the neighbour lists the local searches are restricted to are chosen
+    pub candidates: CandidateKind,
+    /// Length of every neighbour list
+    pub neighbours: usize,
+    /// Most insertions made at once on hull edges that do not affect each other. 1 always
+    /// inserts the single best point, more score the edges they touch in parallel but give
+    /// longer tours
+    pub insert_batch: usize,
+    /// How construction and relp score putting a city into a hull edge
+    pub criterion: CriterionKind,
+    /// Number of constructions to post-process in parallel, keeping the shortest tour. The
+    /// first is the usual one, the others add noise to every insertion score and to the
+    /// constructor, see `Constructor`
+    pub starts: usize,
+    /// Size of that noise as a fraction of the median score of the edges of the convex hull
+    pub noise: f32,
+    /// How the first tour is built, relp scores its cities with the criterion when it is not
+    /// the insertion. Explicit matrices always start from nearest neighbour
+    pub constructor: ConstructorKind,
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        SolverConfig {
+            log: false,
+            post: true,
+            edge_swap: true,
+            two_opt: false,
+            or_opt: true,
+            three_opt: false,
+            relp: true,
+            relp_first: false,
+            lin_kernighan: false,
+            lk_time: None,
+            time_limit: None,
+            seed: 0,
+            candidates: CandidateKind::Nearest,
+            neighbours: 8,
+            insert_batch: 1,
+            criterion: CriterionKind::Lda,
+            starts: 1,
+            noise: 0.3,
+            constructor: ConstructorKind::Insertion,
+        }
+    }
+}
+
+/// Wall clock time spent in each phase of a solve.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PhaseTimings {
+    pub construction: Duration,
+    /// Building the neighbour lists, when a phase needed them
+    pub candidates: Duration,
+    pub edge_swap: Duration,
+    pub or_opt: Duration,
+    pub three_opt: Duration,
+    pub relp: Duration,
+    pub lin_kernighan: Duration,
+    /// The iterated local search that fills the rest of --time-limit
+    pub ils: Duration,
+}
+
+impl PhaseTimings {
+    pub fn post_processing(&self) -> Duration {
+        self.candidates
+            + self.edge_swap
+            + self.or_opt
+            + self.three_opt
+            + self.relp
+            + self.lin_kernighan
+            + self.ils
+    }
+
+    pub fn total(&self) -> Duration {
+        self.construction + self.post_processing()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Solution {
+    /// Indices into the solved points in tour order, the tour closes from the last index back to the first
+    pub tour: Vec<usize>,
+    /// Length of the final tour
+    pub length: f32,
+    /// Length of the final tour with every edge rounded by the TSPLIB rules for the metric
+    pub tsplib_length: i64,
+    /// Length of the tour straight out of the construction, before any post-processing
+    pub construction_length: f32,
+    /// With several starts these are the phases of the start that was kept
+    pub timings: PhaseTimings,
+    /// TSPLIB length of the tour every start ended with, in start order
+    pub start_lengths: Vec<i64>,
+}
+
+pub struct Solver {
+    config: SolverConfig,
+}
+
+impl Solver {
+    pub fn new(config: SolverConfig) -> Self {
+        Solver { config }
+    }
+
+    pub fn config(&self) -> &SolverConfig {
+        &self.config
+    }
+
+    /// Solves plain points under Euclidean distance
+    pub fn solve(&self, points: &[shared::Point]) -> Solution {
+        self.solve_with_metric(points, Metric::Euc2d)
+    }
+
+    /// Solves a parsed problem under the distance function it declares
+    pub fn solve_instance(&self, instance: &Instance) -> Solution {
+        match &instance.matrix {
+            Some(distances) => self.solve_matrix(distances),
+            None => self.solve_with_metric(&instance.points, instance.metric),
+        }
+    }
+
+    /// Solves an explicit distance matrix. With no geometry there is no hull to grow or LDA
+    /// to rank by, so the tour comes from nearest neighbour and is improved by 2-opt (in place
+    /// of crossing elimination) and or-opt, the same searches as for coordinates with the
+    /// matrix as distance and candidates taken from its rows. Relp needs insertion LDAs and is
+    /// skipped. Only post, edge_swap, or_opt and neighbours of the config apply, the command
+    /// line rejects the rest.
+    pub fn solve_matrix(&self, distances: &DistanceMatrix) -> Solution {
+        let mut timings = PhaseTimings::default();
+        let n = distances.len();
+
+        let start = Instant::now();
+        let mut tour = matrix::nearest_neighbour_tour(distances);
+        timings.construction = start.elapsed();
+        let construction_length = distances.tour_length(&tour);
+
+        if self.config.post && (self.config.edge_swap || self.config.or_opt) {
+            let d = |a: usize, b: usize| distances.dist(a, b) as f64;
+            let phase_start = Instant::now();
+            let candidates = Candidates::from_matrix(distances, self.config.neighbours);
+            timings.candidates = phase_start.elapsed();
+
+            if self.config.edge_swap {
+                let phase_start = Instant::now();
+                let mut array_tour = ArrayTour::new(tour);
+                let start = array_tour.order().to_vec();
+                two_opt::two_opt_by(&mut array_tour, d, &candidates, &start);
+                tour = array_tour.into_order();
+                timings.edge_swap = phase_start.elapsed();
+            }
+            if self.config.or_opt {
+                let phase_start = Instant::now();
+                let mut linked = Tour::from_order(n, &tour);
+                or_opt::or_opt_by(&mut linked, d, &candidates);
+                tour = linked.order();
+                timings.or_opt = phase_start.elapsed();
+            }
+        }
+
+        let tsplib_length = distances.tsplib_tour_length(&tour);
+        Solution {
+            length: distances.tour_length(&tour),
+            tsplib_length,
+            tour,
+            construction_length,
+            timings,
+            start_lengths: vec![tsplib_length],
+        }
+    }
+
+    pub fn solve_with_metric(&self, points: &[shared::Point], metric: Metric) -> Solution {
+        // Coincident cities are solved as one and put back next to each other at the end,
+        // where the zero length edges between them cost nothing. Left in, they would give the
+        // hull and the insertion scores zero length sides to work with
+        let (unique, copies) = group_duplicates(points);
+        let mut solution = self.solve_unique(&unique, metric);
+        solution.tour = solution
+            .tour
+            .iter()
+            .flat_map(|&i| copies[i].iter().copied())
+            .collect();
+        solution
+    }
+
+    fn solve_unique(&self, points: &[shared::Point], metric: Metric) -> Solution {
+        if points.len() < 4 {
+            // Every ordering of three or fewer points is the same tour
+            let tour: Vec<usize> = (0..points.len()).collect();
+            let length = tour_length(&tour, points, metric);
+            let tsplib_length = math::tsplib_path_dist(points, metric);
+            return Solution {
+                tour,
+                length,
+                tsplib_length,
+                construction_length: length,
+                timings: PhaseTimings::default(),
+                start_lengths: vec![tsplib_length],
+            };
+        }
+
+        let start = Instant::now();
+        let time_limit = self.config.time_limit;
+        if self.config.starts <= 1 {
+            return self.solve_start(
+                points,
+                metric,
+                self.config.seed,
+                0.0,
+                self.config.log,
+                time_limit.map(|time| start + time),
+            );
+        }
+        // The starts that cannot run at the same time split the time limit between them, each
+        // counting its share from when it begins so the last one still ends on time
+        let rounds = self.config.starts.div_ceil(rayon::current_num_threads());
+        // The first start is the plain construction, so more starts never give a longer tour
+        let solutions: Vec<Solution> = (0..self.config.starts)
+            .into_par_iter()
+            .map(|i| {
+                let (seed, noise) = if i == 0 {
+                    (self.config.seed, 0.0)
+                } else {
+                    (
+                        shared::mix_seed(self.config.seed, 2 + i as u64),
+                        self.config.noise,
+                    )
+                };
+                let deadline = time_limit
+                    .map(|time| (Instant::now() + time / rounds as u32).min(start + time));
+                self.solve_start(points, metric, seed, noise, false, deadline)
+            })
+            .collect();
+        let start_lengths = solutions.iter().map(|s| s.tsplib_length).collect();
+        let mut best = solutions
+            .into_iter()
+            .min_by_key(|solution| solution.tsplib_length)
+            .unwrap();
+        best.start_lengths = start_lengths;
+        best
+    }
+
+    // One construction and its post-processing, with the construction noisy by noise. The
+    // iterated local search runs until deadline
+    fn solve_start(
+        &self,
+        points: &[shared::Point],
+        metric: Metric,
+        seed: u64,
+        noise: f32,
+        log: bool,
+        deadline: Option<Instant>,
+    ) -> Solution {
+        let mut timings = PhaseTimings::default();
+        let construction_start = Instant::now();
+        let n = points.len();
+
+        let (mut tour, mut insert_log, adaptive_n) = match self.config.constructor.constructor() {
+            Some(constructor) => {
+                let tour = constructor.construct(points, metric, noise, seed);
+                let criterion = self.config.criterion.criterion();
+                let insert_log = relp::score_tour(&tour, points, metric, criterion);
+                (tour, insert_log, (64_usize).min(n / 10).max(8))
+            }
+            None => self.insert_from_hull(points, metric, seed, noise, log),
+        };
+        timings.construction = construction_start.elapsed();
+        let construction_length = tour_length(&tour, points, metric);
+
+        if self.config.post {
+            // Relp reinserts the cities the construction scored worst, on request before the
+            // local searches so they polish the cities it moved
+            if self.config.relp && self.config.relp_first {
+                let phase_start = Instant::now();
+                tour = self.relp(
+                    tour,
+                    points,
+                    metric,
+                    &mut insert_log,
+                    adaptive_n,
+                    noise,
+                    seed,
+                );
+                timings.relp = phase_start.elapsed();
+            }
+
+            // Built once for every phase that searches the neighbour lists
+            let mut candidates = Candidates::default();
+            if (self.config.edge_swap && self.config.two_opt)
+                || self.config.or_opt
+                || self.config.three_opt
+                || self.config.lin_kernighan
+                || self.config.time_limit.is_some()
+            {
+                let phase_start = Instant::now();
+                candidates = Candidates::build(
+                    points,
+                    metric,
+                    self.config.candidates,
+                    self.config.neighbours,
+                );
+                timings.candidates = phase_start.elapsed();
+            }
+
+            if self.config.edge_swap && self.config.two_opt {
+                let phase_start = Instant::now();
+                let mut array_tour = ArrayTour::new(tour);
+                two_opt::two_opt(&mut array_tour, points, metric, &candidates);
+                tour = array_tour.into_order();
+                timings.edge_swap = phase_start.elapsed();
+            } else if self.config.edge_swap {
+                let phase_start = Instant::now();
+                let mut array_tour = ArrayTour::new(tour);
+                edges::eliminate_all_crossings(&mut array_tour, points, metric);
+                tour = array_tour.into_order();
+                timings.edge_swap = phase_start.elapsed();
+            }
+            if self.config.or_opt {
+                let phase_start = Instant::now();
+                let mut linked = Tour::from_order(n, &tour);
+                or_opt::or_opt(&mut linked, points, metric, &candidates);
+                tour = linked.order();
+                timings.or_opt = phase_start.elapsed();
+            }
+            if self.config.three_opt {
+                let phase_start = Instant::now();
+                let mut array_tour = ArrayTour::new(tour);
+                three_opt::three_opt(&mut array_tour, points, metric, &candidates);
+                tour = array_tour.into_order();
+                timings.three_opt = phase_start.elapsed();
+            }
+            if self.config.relp && !self.config.relp_first {
+                let phase_start = Instant::now();
+                tour = self.relp(
+                    tour,
+                    points,
+                    metric,
+                    &mut insert_log,
+                    adaptive_n,
+                    noise,
+                    seed,
+                );
+                timings.relp = phase_start.elapsed();
+            }
+            if self.config.lin_kernighan {
+                let phase_start = Instant::now();
+                let lk_deadline = match (self.config.lk_time, deadline) {
+                    (Some(time), Some(deadline)) => Some(deadline.min(phase_start + time)),
+                    (Some(time), None) => Some(phase_start + time),
+                    (None, deadline) => deadline,
+                };
+                let mut array_tour = ArrayTour::new(tour);
+                lin_kernighan::lin_kernighan(
+                    &mut array_tour,
+                    points,
+                    metric,
+                    &candidates,
+                    lk_deadline,
+                );
+                tour = array_tour.into_order();
+                timings.lin_kernighan = phase_start.elapsed();
+            }
+            if let Some(deadline) = deadline {
+                let phase_start = Instant::now();
+                let mut array_tour = ArrayTour::new(tour);
+                // The strongest local search that was asked for repairs every kick
+                let (lk, three) = (self.config.lin_kernighan, self.config.three_opt);
+                let kicks = ils::iterated_local_search(
+                    &mut array_tour,
+                    points,
+                    metric,
+                    deadline,
+                    shared::mix_seed(seed, 2),
+                    |tour, changed| {
+                        if lk {
+                            lin_kernighan::lin_kernighan_from(
+                                tour,
+                                points,
+                                metric,
+                                &candidates,
+                                changed,
+                                Some(deadline),
+                            )
+                        } else if three {
+                            three_opt::three_opt_from(tour, points, metric, &candidates, changed)
+                        } else {
+                            two_opt::two_opt_from(tour, points, metric, &candidates, changed)
+                        }
+                    },
+                );
+                tour = array_tour.into_order();
+                timings.ils = phase_start.elapsed();
+                if log {
+                    eprintln!("Iterated local search tried {} kicks", kicks);
+                }
+            }
+        }
+
+        let tsplib_length = tsplib_tour_length(&tour, points, metric);
+        Solution {
+            length: tour_length(&tour, points, metric),
+            tsplib_length,
+            tour,
+            construction_length,
+            timings,
+            start_lengths: vec![tsplib_length],
+        }
+    }
+
+    // Takes the cities with the lowest scores in insert_log out of tour and inserts them again
+    fn relp(
+        &self,
+        tour: Vec<usize>,
+        points: &[shared::Point],
+        metric: Metric,
+        insert_log: &mut Vec<relp::InsertPointResult>,
+        adaptive_n: usize,
+        noise: f32,
+        seed: u64,
+    ) -> Vec<usize> {
+        // Rebuild spatial grid for re-optimization phase
+        let new_inner_hull = find_lowest_lda_points(insert_log, tour.len() / 8);
+        let mut reopt_spatial_grid = SpatialGrid::new(points, &new_inner_hull);
+
+        let mut hull = Tour::from_order(points.len(), &tour);
+        remove_points_from_hull(&mut hull, &new_inner_hull);
+
+        insert_all(
+            &mut hull,
+            points,
+            &mut reopt_spatial_grid,
+            insert_log,
+            adaptive_n,
+            self.config.insert_batch,
+            self.config.criterion.criterion(),
+            noise,
+            metric,
+            shared::mix_seed(seed, 1),
+            &ProgressBar::hidden(),
+        );
+        hull.order()
+    }
+
+    // Grows the convex hull into a tour by insertion, returning it with the insertion log and
+    // the number of candidates each edge looked at
+    fn insert_from_hull(
+        &self,
+        points: &[shared::Point],
+        metric: Metric,
+        seed: u64,
+        noise: f32,
+        log: bool,
+    ) -> (Vec<usize>, Vec<relp::InsertPointResult>, usize) {
+        let n = points.len();
+        let hull_order = math::convex_hull_indices(points);
+        let mut on_hull = vec![false; n];
+        for &city in &hull_order {
+            on_hull[city] = true;
+        }
+        let inner_hull: Vec<usize> = (0..n).filter(|&city| !on_hull[city]).collect();
+        let mut hull = Tour::from_order(n, &hull_order);
+        let mut insert_log: Vec<relp::InsertPointResult> = Vec::with_capacity(inner_hull.len());
+
+        // Build spatial grid instead of kdtree
+        // Remove hull points from spatial grid since they're not "inner" points
+        let all: Vec<usize> = (0..n).collect();
+        let mut spatial_grid = SpatialGrid::new(points, &all);
+        for &hull_point in &hull_order {
+            spatial_grid.remove_point(hull_point);
+        }
+
+        let pb = if log {
+            ProgressBar::new(inner_hull.len() as u64)
+        } else {
+            ProgressBar::hidden()
+        };
+
+        let adaptive_n = (64_usize).min(inner_hull.len() / 10).max(8);
+        insert_all(
+            &mut hull,
+            points,
+            &mut spatial_grid,
+            &mut insert_log,
+            adaptive_n,
+            self.config.insert_batch,
+            self.config.criterion.criterion(),
+            noise,
+            metric,
+            shared::mix_seed(seed, 0),
+            &pb,
+        );
+        pb.finish();
+
+        (hull.order(), insert_log, adaptive_n)
+    }
+}
+
+impl Solution {
+    /// The solved points in tour order
+    pub fn tour_points(&self, points: &[shared::Point]) -> Vec<shared::Point> {
+        self.tour.iter().map(|&i| points[i]).collect()
+    }
+}
+
+// Splits points into the distinct coordinates, in order of first appearance, and the input
+// indices of every city at each of them
+fn group_duplicates(points: &[shared::Point]) -> (Vec<shared::Point>, Vec<Vec<usize>>) {
+    let mut index_of: HashMap<shared::Point, usize> = HashMap::default();
+    let mut unique = Vec::with_capacity(points.len());
+    let mut copies: Vec<Vec<usize>> = Vec::with_capacity(points.len());
+    for (i, &point) in points.iter().enumerate() {
+        // Adding 0.0 turns -0.0 into 0.0, which compare equal but hash differently
+        let point = shared::Point {
+            x: point.x + 0.0,
+            y: point.y + 0.0,
+        };
+        let next = unique.len();
+        let id = *index_of.entry(point).or_insert(next);
+        if id == next {
+            unique.push(point);
+            copies.push(Vec::new());
+        }
+        copies[id].push(i);
+    }
+    (unique, copies)
+}
+
+fn tour_points(tour: &[usize], points: &[shared::Point]) -> Vec<shared::Point> {
+    tour.iter().map(|&i| points[i]).collect()
+}
+
+fn tour_length(tour: &[usize], points: &[shared::Point], metric: Metric) -> f32 {
+    if tour.is_empty() {
+        return 0.0;
+    }
+    math::path_dist(&tour_points(tour, points), metric)
+}
+
+fn tsplib_tour_length(tour: &[usize], points: &[shared::Point], metric: Metric) -> i64 {
+    math::tsplib_path_dist(&tour_points(tour, points), metric)
+}
@@ -0,0 +1,218 @@
+// Sequential 3-opt over candidate neighbour lists with don't-look bits. Every move is written
+// in the usual Lin-Kernighan names: the edge (t1, t2) goes, t2 joins t3, the edge (t3, t4)
+// goes, t4 joins t5, the edge (t5, t6) goes and t6 closes the tour back to t1
+
+use std::collections::VecDeque;
+
+use crate::candidates::Candidates;
+use crate::metric::Metric;
+use crate::shared::Point;
+use crate::tour::ArrayTour;
+use crate::two_opt::MIN_GAIN;
+
+// The reconnections of the removed edges, each one applied as a few 2-opt moves
+#[derive(Debug, Clone, Copy)]
+enum Move {
+    /// Closing with (t4, t1) right away
+    TwoOpt,
+    /// t4 follows t3, the tour becomes t1 t4 ... t2 t3 and then a second 2-opt move on that
+    Sequential,
+    /// t4 follows t3 so t2 ... t3 is cut off into a cycle and (t5, t6) reopens it. With t6
+    /// after t5 the two parts of t2 ... t3 swap places
+    SegmentSwap,
+    /// The same with t6 before t5, both parts stay in place but are reversed
+    SegmentReversal,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Found {
+    gain: f64,
+    kind: Move,
+    t: [usize; 6],
+}
+
+/// Applies improving 2-opt and sequential 3-opt moves until none is left and returns how
+/// much shorter the tour got. The cities that may
+/// join t2 and t4 come from their candidate lists, and only while the running gain stays
+/// positive, so every prefix of a move has to pay for itself as in Lin-Kernighan. A city is
+/// only looked at again once one of its tour edges changed.
+pub fn three_opt(
+    tour: &mut ArrayTour,
+    points: &[Point],
+    metric: Metric,
+    candidates: &Candidates,
+) -> f64 {
+    let start = tour.order().to_vec();
+    return three_opt_from(tour, points, metric, candidates, &start);
+}
+
+/// The same search starting from the cities of start only
+pub fn three_opt_from(
+    tour: &mut ArrayTour,
+    points: &[Point],
+    metric: Metric,
+    candidates: &Candidates,
+    start: &[usize],
+) -> f64 {
+    let n = tour.len();
+    if n < 8 {
+        return 0.0;
+    }
+    let d = |a: usize, b: usize| metric.dist(points[a], points[b]) as f64;
+
+    let mut queued = vec![false; n];
+    let mut queue = VecDeque::with_capacity(start.len());
+    for &city in start {
+        if !queued[city] {
+            queued[city] = true;
+            queue.push_back(city);
+        }
+    }
+    let mut total_gain = 0.0;
+
+    while let Some(t1) = queue.pop_front() {
+        queued[t1] = false;
+        for forward in [true, false] {
+            let Some(found) = find_move(tour, candidates, &d, t1, forward) else {
+                continue;
+            };
+            apply(tour, found);
+            for city in found.t {
+                if !queued[city] {
+                    queued[city] = true;
+                    queue.push_back(city);
+                }
+            }
+            total_gain += found.gain;
+            break;
+        }
+    }
+    return total_gain;
+}
+
+// The first t3 that gives an improving move from (t1, t2), with its best closing
+fn find_move(
+    tour: &ArrayTour,
+    candidates: &Candidates,
+    d: &impl Fn(usize, usize) -> f64,
+    t1: usize,
+    forward: bool,
+) -> Option<Found> {
+    // The tour read in the direction of the search, t2 comes after t1
+    let succ = |city: usize| {
+        if forward {
+            tour.next(city)
+        } else {
+            tour.prev(city)
+        }
+    };
+    let pred = |city: usize| {
+        if forward {
+            tour.prev(city)
+        } else {
+            tour.next(city)
+        }
+    };
+    let between = |a: usize, b: usize, c: usize| {
+        if forward {
+            tour.between(a, b, c)
+        } else {
+            tour.between(c, b, a)
+        }
+    };
+
+    let t2 = succ(t1);
+    let d12 = d(t1, t2);
+    for &t3 in candidates.of(t2) {
+        let g1 = d12 - d(t2, t3);
+        // Lists are sorted, no later candidate keeps the gain positive either
+        if g1 <= MIN_GAIN {
+            break;
+        }
+        if t3 == t1 || t3 == succ(t2) {
+            continue;
+        }
+
+        let mut best: Option<Found> = None;
+        let mut consider = |gain: f64, kind: Move, t: [usize; 6]| {
+            if gain > MIN_GAIN && best.is_none_or(|b| gain > b.gain) {
+                best = Some(Found { gain, kind, t });
+            }
+        };
+
+        // t4 before t3 leaves the path t4 ... t2 t3 ... t1 that (t4, t1) closes
+        let t4 = pred(t3);
+        let g2 = g1 + d(t3, t4);
+        consider(g2 - d(t4, t1), Move::TwoOpt, [t1, t2, t3, t4, t4, t4]);
+        for &t5 in candidates.of(t4) {
+            let g3 = g2 - d(t4, t5);
+            if g3 <= MIN_GAIN {
+                break;
+            }
+            if t5 == t1 || t5 == t3 {
+                continue;
+            }
+            // The tour t1 t4 ... t2 t3 ... runs backwards between t4 and t2, t6 has to come
+            // before t5 in it
+            let t6 = if between(t2, t5, t4) {
+                succ(t5)
+            } else {
+                pred(t5)
+            };
+            if t6 == t4 {
+                continue;
+            }
+            consider(
+                g3 + d(t5, t6) - d(t6, t1),
+                Move::Sequential,
+                [t1, t2, t3, t4, t5, t6],
+            );
+        }
+
+        // t4 after t3 cuts t2 ... t3 off, t5 has to lie on it to join it back up
+        let t4 = succ(t3);
+        if t4 != t1 {
+            let g2 = g1 + d(t3, t4);
+            for &t5 in candidates.of(t4) {
+                let g3 = g2 - d(t4, t5);
+                if g3 <= MIN_GAIN {
+                    break;
+                }
+                if !between(t2, t5, t3) {
+                    continue;
+                }
+                for (t6, kind) in [
+                    (succ(t5), Move::SegmentSwap),
+                    (pred(t5), Move::SegmentReversal),
+                ] {
+                    if (t5 == t3 && t6 == t4) || (t5 == t2 && t6 == t1) {
+                        continue;
+                    }
+                    consider(g3 + d(t5, t6) - d(t6, t1), kind, [t1, t2, t3, t4, t5, t6]);
+                }
+            }
+        }
+
+        if best.is_some() {
+            return best;
+        }
+    }
+    None
+}
+
+fn apply(tour: &mut ArrayTour, found: Found) {
+    let [t1, t2, t3, t4, t5, t6] = found.t;
+    match found.kind {
+        Move::TwoOpt => tour.two_opt_move(t1, t2, t3, t4),
+        Move::Sequential => {
+            tour.two_opt_move(t1, t2, t3, t4);
+            tour.two_opt_move(t1, t4, t5, t6);
+        }
+        Move::SegmentSwap => tour.swap_segments(t1, t2, t3, t4, t5, t6),
+        Move::SegmentReversal => {
+            // t1 [t2 .. t6] [t5 .. t3] t4 becomes t1 [t6 .. t2] [t3 .. t5] t4
+            tour.two_opt_move(t1, t2, t5, t6);
+            tour.two_opt_move(t4, t3, t2, t5);
+        }
+    }
+}
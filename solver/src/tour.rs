@@ -0,0 +1,285 @@
+// Tours as indices into the solved points
+
+const NONE: usize = usize::MAX;
+
+/// A cyclic tour over a subset of the cities 0..n, stored as a doubly linked list so a city
+/// can be inserted after any other or taken out again in O(1), without searching for it
+#[derive(Debug, Clone)]
+pub struct Tour {
+    next: Vec<usize>,
+    prev: Vec<usize>,
+    /// The cities currently in the tour, in the order they joined it
+    cities: Vec<usize>,
+}
+
+impl Tour {
+    /// Links `order` into a tour of cities drawn from 0..n
+    pub fn from_order(n: usize, order: &[usize]) -> Self {
+        let mut tour = Tour {
+            next: vec![NONE; n],
+            prev: vec![NONE; n],
+            cities: order.to_vec(),
+        };
+        let len = order.len();
+        for i in 0..len {
+            let city = order[i];
+            let next = order[(i + 1) % len];
+            tour.next[city] = next;
+            tour.prev[next] = city;
+        }
+        return tour;
+    }
+
+    pub fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cities.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn contains(&self, city: usize) -> bool {
+        self.next[city] != NONE
+    }
+
+    #[inline(always)]
+    pub fn next(&self, city: usize) -> usize {
+        self.next[city]
+    }
+
+    #[inline(always)]
+    pub fn prev(&self, city: usize) -> usize {
+        self.prev[city]
+    }
+
+    /// The cities in the tour in no particular order, every edge of the tour is
+    /// `(city, next(city))` for exactly one of them
+    pub fn cities(&self) -> &[usize] {
+        &self.cities
+    }
+
+    /// Puts city, which must not be in the tour yet, between after and its successor
+    pub fn insert_after(&mut self, after: usize, city: usize) {
+        debug_assert!(self.contains(after) && !self.contains(city));
+        let next = self.next[after];
+        self.next[after] = city;
+        self.prev[city] = after;
+        self.next[city] = next;
+        self.prev[next] = city;
+        self.cities.push(city);
+    }
+
+    /// Takes every city of to_remove out of the tour, joining up their neighbours
+    pub fn remove_all(&mut self, to_remove: &[usize]) {
+        for &city in to_remove {
+            if !self.contains(city) {
+                continue;
+            }
+            let (prev, next) = (self.prev[city], self.next[city]);
+            self.next[prev] = next;
+            self.prev[next] = prev;
+            self.next[city] = NONE;
+            self.prev[city] = NONE;
+        }
+        let next = &self.next;
+        self.cities.retain(|&city| next[city] != NONE);
+    }
+
+    /// Cuts the path forward from first to last out of the tour and puts it back between
+    /// after and its successor, running from last back to first when reversed. after must
+    /// not be on the path. Costs the length of the path, and nothing else when not reversed
+    pub fn move_segment(&mut self, first: usize, last: usize, after: usize, reversed: bool) {
+        let (prev, next) = (self.prev[first], self.next[last]);
+        self.next[prev] = next;
+        self.prev[next] = prev;
+
+        let (head, tail) = if reversed {
+            let mut city = first;
+            loop {
+                std::mem::swap(&mut self.next[city], &mut self.prev[city]);
+                if city == last {
+                    break;
+                }
+                // The old successor is now stored as prev
+                city = self.prev[city];
+            }
+            (last, first)
+        } else {
+            (first, last)
+        };
+
+        let before = self.next[after];
+        self.next[after] = head;
+        self.prev[head] = after;
+        self.next[tail] = before;
+        self.prev[before] = tail;
+    }
+
+    /// The cities in tour order, starting from the first city that is still in the tour
+    pub fn order(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.len());
+        let Some(&first) = self.cities.first() else {
+            return order;
+        };
+        let mut city = first;
+        loop {
+            order.push(city);
+            city = self.next[city];
+            if city == first {
+                break;
+            }
+        }
+        return order;
+    }
+}
+
+/// A tour as an array of cities together with the position of every city in it, for the
+/// local searches that need to know which way round the tour runs and reverse parts of it.
+/// Holds every city 0..n
+#[derive(Debug, Clone)]
+pub struct ArrayTour {
+    order: Vec<usize>,
+    pos: Vec<usize>,
+    // (first position, length) of every reversal since the last checkpoint, while recording
+    journal: Vec<(usize, usize)>,
+    recording: bool,
+}
+
+impl ArrayTour {
+    /// order must be a permutation of 0..order.len()
+    pub fn new(order: Vec<usize>) -> Self {
+        let mut pos = vec![0; order.len()];
+        for (i, &city) in order.iter().enumerate() {
+            pos[city] = i;
+        }
+        return ArrayTour {
+            order,
+            pos,
+            journal: Vec::new(),
+            recording: false,
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    pub fn into_order(self) -> Vec<usize> {
+        self.order
+    }
+
+    #[inline(always)]
+    pub fn pos(&self, city: usize) -> usize {
+        self.pos[city]
+    }
+
+    #[inline(always)]
+    pub fn next(&self, city: usize) -> usize {
+        let i = self.pos[city] + 1;
+        self.order[if i == self.order.len() { 0 } else { i }]
+    }
+
+    #[inline(always)]
+    pub fn prev(&self, city: usize) -> usize {
+        let i = self.pos[city];
+        self.order[if i == 0 { self.order.len() - 1 } else { i - 1 }]
+    }
+
+    /// Whether b lies on the way forward from a to c, a and c included
+    #[inline(always)]
+    pub fn between(&self, a: usize, b: usize, c: usize) -> bool {
+        let (a, b, c) = (self.pos[a], self.pos[b], self.pos[c]);
+        if a <= c {
+            a <= b && b <= c
+        } else {
+            b >= a || b <= c
+        }
+    }
+
+    /// Replaces the edges (t1, t2) and (t3, t4) with (t2, t3) and (t4, t1), where t2 follows t1
+    /// and t4 comes before t3 reading the tour one way or the other
+    pub fn two_opt_move(&mut self, t1: usize, t2: usize, t3: usize, t4: usize) {
+        debug_assert!(self.next(t4) == t3 || self.prev(t4) == t3);
+        if self.next(t1) == t2 {
+            // t1 t2 ... t4 t3 becomes t1 t4 ... t2 t3
+            self.reverse(t2, t4);
+        } else {
+            // t3 t4 ... t2 t1 becomes t3 t2 ... t4 t1
+            self.reverse(t4, t2);
+        }
+    }
+
+    /// Swaps the neighbouring paths t2 .. t5 and t6 .. t3 that lie between t1 and t4, reading
+    /// the tour one way or the other: t1 [t2 .. t5] [t6 .. t3] t4 becomes
+    /// t1 [t6 .. t3] [t2 .. t5] t4, with both paths still running the same way
+    pub fn swap_segments(
+        &mut self,
+        t1: usize,
+        t2: usize,
+        t3: usize,
+        t4: usize,
+        t5: usize,
+        t6: usize,
+    ) {
+        self.two_opt_move(t1, t2, t4, t3);
+        self.two_opt_move(t1, t3, t5, t6);
+        self.two_opt_move(t3, t5, t4, t2);
+    }
+
+    /// Reverses the path forward from `from` to `to`. When the rest of the tour is shorter
+    /// that is reversed instead, which gives the same tour running the other way round, so
+    /// callers must not rely on the direction afterwards
+    pub fn reverse(&mut self, from: usize, to: usize) {
+        let n = self.order.len();
+        let (mut i, mut j) = (self.pos[from], self.pos[to]);
+        let mut len = (j + n - i) % n + 1;
+        if 2 * len > n {
+            // The complement runs from the city after `to` to the city before `from`
+            (i, j) = ((j + 1) % n, (i + n - 1) % n);
+            len = n - len;
+        }
+        if self.recording {
+            self.journal.push((i, len));
+        }
+        self.reverse_positions(i, j, len);
+    }
+
+    /// Starts remembering every change to the tour so rollback can take them back, forgetting
+    /// the ones before. Undoing costs as much as the changes did, not a copy of the tour
+    pub fn checkpoint(&mut self) {
+        self.journal.clear();
+        self.recording = true;
+    }
+
+    /// Puts the tour back the way it was at the last checkpoint and keeps recording from there
+    pub fn rollback(&mut self) {
+        let n = self.order.len();
+        while let Some((i, len)) = self.journal.pop() {
+            // Reversing the same positions again is its own undo
+            self.reverse_positions(i, (i + len + n - 1) % n, len);
+        }
+    }
+
+    // Reverses the len positions from i forward to j, wrapping around the end of the array
+    fn reverse_positions(&mut self, mut i: usize, mut j: usize, len: usize) {
+        let n = self.order.len();
+        for _ in 0..len / 2 {
+            let (a, b) = (self.order[i], self.order[j]);
+            self.order[i] = b;
+            self.order[j] = a;
+            self.pos[b] = i;
+            self.pos[a] = j;
+            i = if i + 1 == n { 0 } else { i + 1 };
+            j = if j == 0 { n - 1 } else { j - 1 };
+        }
+    }
+}
@@ -0,0 +1,101 @@
+// 2-opt local search over candidate neighbour lists with don't-look bits
+
+use std::collections::VecDeque;
+
+use crate::candidates::Candidates;
+use crate::metric::Metric;
+use crate::shared::Point;
+use crate::tour::ArrayTour;
+
+// Gains are summed in f64 from the f32 distances so a move and its undo can never both
+// look improving, which would loop forever
+pub(crate) const MIN_GAIN: f64 = 1e-7;
+
+/// Applies improving 2-opt moves until none is left and returns how much shorter the tour
+/// got. For every city a and both of its tour edges (a, b) the search only tries connecting
+/// a to one of its candidates c that is closer than b, which is necessary for the move to
+/// gain anything. A city is only looked at again once one of its tour edges changed.
+pub fn two_opt(
+    tour: &mut ArrayTour,
+    points: &[Point],
+    metric: Metric,
+    candidates: &Candidates,
+) -> f64 {
+    let start = tour.order().to_vec();
+    return two_opt_from(tour, points, metric, candidates, &start);
+}
+
+/// The same search, looking at the cities of start first and then only at the ones whose
+/// tour edges changed, for a tour that is already 2-optimal everywhere else
+pub fn two_opt_from(
+    tour: &mut ArrayTour,
+    points: &[Point],
+    metric: Metric,
+    candidates: &Candidates,
+    start: &[usize],
+) -> f64 {
+    let d = |a: usize, b: usize| metric.dist(points[a], points[b]) as f64;
+    return two_opt_by(tour, d, candidates, start);
+}
+
+/// The search of two_opt_from under any distance between cities, like that of an explicit
+/// matrix, as long as it is symmetric and the candidate lists are sorted by it
+pub fn two_opt_by(
+    tour: &mut ArrayTour,
+    d: impl Fn(usize, usize) -> f64,
+    candidates: &Candidates,
+    start: &[usize],
+) -> f64 {
+    // Four cities are the fewest with two edges that share no city
+    let n = tour.len();
+    if n < 4 {
+        return 0.0;
+    }
+
+    let mut queued = vec![false; n];
+    let mut queue = VecDeque::with_capacity(start.len());
+    for &city in start {
+        if !queued[city] {
+            queued[city] = true;
+            queue.push_back(city);
+        }
+    }
+    let mut total_gain = 0.0;
+
+    while let Some(a) = queue.pop_front() {
+        queued[a] = false;
+        'directions: for forward in [true, false] {
+            let b = if forward { tour.next(a) } else { tour.prev(a) };
+            let d_ab = d(a, b);
+            for &c in candidates.of(a) {
+                let d_ac = d(a, c);
+                // Lists are sorted, no later candidate can gain either
+                if d_ac >= d_ab {
+                    break;
+                }
+                let e = if forward { tour.next(c) } else { tour.prev(c) };
+                if c == b || e == a {
+                    continue;
+                }
+                let gain = d_ab + d(c, e) - d_ac - d(b, e);
+                if gain > MIN_GAIN {
+                    // a b ... c e becomes a c ... b e, and b a ... e c becomes b e ... a c
+                    if forward {
+                        tour.reverse(b, c);
+                    } else {
+                        tour.reverse(a, e);
+                    }
+                    for city in [a, b, c, e] {
+                        if !queued[city] {
+                            queued[city] = true;
+                            queue.push_back(city);
+                        }
+                    }
+                    total_gain += gain;
+                    break 'directions;
+                }
+            }
+        }
+    }
+    return total_gain;
+}
@@ -0,0 +1,73 @@
+// The local searches only ever look at these lists, so a wrong or missing neighbour quietly
+// costs tour quality rather than failing anywhere
+
+use tsp_rust::candidates::{CandidateKind, Candidates};
+use tsp_rust::{Metric, Point};
+
+// A 5 by 5 grid, 10 apart across and 7 apart up, city 12 in the middle
+fn grid() -> Vec<Point> {
+    (0..25)
+        .map(|i| Point {
+            x: (i % 5) as f32 * 10.0,
+            y: (i / 5) as f32 * 7.0,
+        })
+        .collect()
+}
+
+#[test]
+fn nearest_neighbours_closest_first() {
+    let points = grid();
+    let candidates = Candidates::build(&points, Metric::Euc2d, CandidateKind::Nearest, 6);
+    // Above and below, then left and right, then the two lowest of the four diagonals
+    assert_eq!(candidates.of(12), [7, 17, 11, 13, 6, 8]);
+    assert_eq!(candidates.of(0), [5, 1, 6, 10, 11, 2]);
+
+    // Every list is the k closest by brute force, equal distances going to the lowest city
+    for k in [1, 4, 8] {
+        let candidates = Candidates::build(&points, Metric::Euc2d, CandidateKind::Nearest, k);
+        assert_eq!(candidates.len(), points.len());
+        for city in 0..points.len() {
+            let mut others: Vec<usize> = (0..points.len()).filter(|&o| o != city).collect();
+            let d = |o: usize| Metric::Euc2d.dist(points[city], points[o]);
+            others.sort_by(|&a, &b| d(a).total_cmp(&d(b)).then(a.cmp(&b)));
+            assert_eq!(candidates.of(city), &others[..k], "city {} k {}", city, k);
+        }
+    }
+}
+
+#[test]
+fn quadrant_neighbours_spread_around_the_city() {
+    let points = grid();
+    let candidates = Candidates::build(&points, Metric::Euc2d, CandidateKind::Quadrant, 4);
+    // The right neighbour 13 shares a quadrant with 17 above, so 6 down and left takes its
+    // place
+    assert_eq!(candidates.of(12), [7, 17, 11, 6]);
+    // A corner has a single quadrant, the rest is topped up with the closest cities
+    assert_eq!(candidates.of(0), [5, 1, 6, 10]);
+}
+
+#[test]
+fn delaunay_neighbours_include_the_grid_lines() {
+    let points = grid();
+    let candidates = Candidates::build(&points, Metric::Euc2d, CandidateKind::Delaunay, 8);
+    for city in 0..points.len() {
+        let list = candidates.of(city);
+        assert!(!list.contains(&city) && list.len() <= 8, "city {}", city);
+        let (col, row) = (city % 5, city / 5);
+        let mut lines = Vec::new();
+        if row > 0 {
+            lines.push(city - 5);
+        }
+        if row < 4 {
+            lines.push(city + 5);
+        }
+        if col > 0 {
+            lines.push(city - 1);
+        }
+        if col < 4 {
+            lines.push(city + 1);
+        }
+        // The neighbours along the grid lines are the closest Delaunay edges, so they come first
+        assert_eq!(list[..lines.len()], lines, "city {}", city);
+    }
+}
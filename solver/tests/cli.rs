@@ -0,0 +1,209 @@
+// Scripts tell failures apart by the exit code alone, so every kind of failure has to keep its
+// own code and say on stderr what went wrong
+
+mod common;
+
+use std::fs;
+
+use common::{data_path, run};
+use tsp_rust::error::{EXIT_INVALID_TOUR, EXIT_IO, EXIT_MALFORMED, EXIT_UNSUPPORTED, EXIT_USAGE};
+
+// Writes a file for one test to read, named after the test so they can run at once
+fn file(name: &str, text: &str) -> String {
+    let path = format!("{}/{}", env!("CARGO_TARGET_TMPDIR"), name);
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn assert_fails(args: &[&str], code: i32, message: &str) {
+    let output = run(args);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(code), "{:?}: {}", args, stderr);
+    assert!(
+        stderr.contains(message),
+        "{:?}: expected '{}' in {}",
+        args,
+        message,
+        stderr
+    );
+    assert!(output.stdout.is_empty(), "{:?} wrote a tour", args);
+}
+
+#[test]
+fn bad_command_lines() {
+    let berlin52 = data_path("berlin52.tsp");
+    assert_fails(
+        &[&berlin52, "--bogus"],
+        EXIT_USAGE,
+        "error: unknown option '--bogus' for solve",
+    );
+    assert_fails(
+        &[&berlin52, "--seed", "x"],
+        EXIT_USAGE,
+        "error: --seed needs a whole number, got 'x'",
+    );
+    assert_fails(
+        &["validate"],
+        2,
+        "error: validate needs an input and a tour",
+    );
+}
+
+#[test]
+fn input_that_cannot_be_read() {
+    let missing = format!("{}/missing.tsp", env!("CARGO_TARGET_TMPDIR"));
+    let message = format!("error: could not read {}", missing);
+    assert_fails(&[&missing], EXIT_IO, &message);
+}
+
+#[test]
+fn malformed_input() {
+    let path = file(
+        "malformed.tsp",
+        "NAME : x\nTYPE : TSP\nEDGE_WEIGHT_TYPE : EUC_2D\nNODE_COORD_SECTION\n\
+         1 0 0\n2 3 x\n3 1 1\nEOF\n",
+    );
+    let message = format!(
+        "error: {}: line 6 (NODE_COORD_SECTION): expected a number, got 'x'",
+        path
+    );
+    assert_fails(&[&path], EXIT_MALFORMED, &message);
+}
+
+#[test]
+fn unsupported_input() {
+    let path = file(
+        "unsupported.tsp",
+        "NAME : x\nTYPE : TSP\nEDGE_WEIGHT_TYPE : EUC_3D\nNODE_COORD_SECTION\n1 0 0 0\nEOF\n",
+    );
+    let message = format!(
+        "error: {}: line 3 (header): unsupported EDGE_WEIGHT_TYPE 'EUC_3D'",
+        path
+    );
+    assert_fails(&[&path], EXIT_UNSUPPORTED, &message);
+}
+
+#[test]
+fn invalid_tour() {
+    let tour = file("invalid.tour", "TOUR_SECTION\n1\n2\n2\n-1\nEOF\n");
+    let message = format!(
+        "error: {} is not a valid tour: node 2 is visited more than once",
+        tour
+    );
+    let berlin52 = data_path("berlin52.tsp");
+    assert_fails(&["validate", &berlin52, &tour], EXIT_INVALID_TOUR, &message);
+}
+
+// Five cities in a ring, each 10 from its neighbours and 20 from the rest
+const EXPLICIT: &str = "NAME : ring\nTYPE : TSP\nDIMENSION : 5\nEDGE_WEIGHT_TYPE : EXPLICIT
+EDGE_WEIGHT_FORMAT : UPPER_ROW\nEDGE_WEIGHT_SECTION\n10 20 20 10\n10 20 20\n10 20\n10\nEOF\n";
+
+#[test]
+fn flags_an_explicit_instance_would_ignore() {
+    let path = file("explicit.tsp", EXPLICIT);
+    let flags = [
+        &["--lk"][..],
+        &["--time-limit", "1"],
+        &["--starts", "2"],
+        &["--relp-first"],
+    ];
+    for flag in flags {
+        let args: Vec<&str> = [&path[..]]
+            .into_iter()
+            .chain(flag.iter().copied())
+            .collect();
+        let message = format!(
+            "error: {}: {} does not apply to EXPLICIT instances",
+            path, flag[0]
+        );
+        assert_fails(&args, EXIT_USAGE, &message);
+    }
+
+    let output = run(&[&path, "--no-log", "--two-opt"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!output.stdout.is_empty(), "no tour written");
+}
+
+#[test]
+fn help_lists_every_option() {
+    let output = run(&["help"]);
+    assert!(output.status.success(), "{:?}", output);
+    let help = String::from_utf8_lossy(&output.stdout);
+    for option in [
+        "--output",
+        "--format",
+        "--seed",
+        "--threads",
+        "--no-log",
+        "--no-post",
+    ] {
+        assert!(help.contains(option), "{} missing from {}", option, help);
+    }
+}
+
+#[test]
+fn help_for_one_command() {
+    let output = run(&["help", "bench"]);
+    assert!(output.status.success(), "{:?}", output);
+    let help = String::from_utf8_lossy(&output.stdout);
+    for expected in [
+        "tsp_rust bench <INPUT>",
+        "--runs",
+        "--two-opt",
+        "EXIT CODES",
+    ] {
+        assert!(
+            help.contains(expected),
+            "{} missing from {}",
+            expected,
+            help
+        );
+    }
+    assert!(!help.contains("tsp_rust validate"), "{}", help);
+
+    let output = run(&["stats", "--help"]);
+    let help = String::from_utf8_lossy(&output.stdout);
+    assert!(help.contains("tsp_rust stats <INPUT>"), "{}", help);
+    assert!(!help.contains("--two-opt"), "{}", help);
+
+    assert_fails(
+        &["help", "bogus"],
+        EXIT_USAGE,
+        "error: unknown command 'bogus', see tsp_rust help",
+    );
+    assert_fails(
+        &["help", "solve", "bench"],
+        EXIT_USAGE,
+        "error: unexpected argument 'bench'",
+    );
+}
+
+// --no-log leaves stderr to the errors, with or without post-processing
+#[test]
+fn no_log_is_quiet() {
+    let berlin52 = data_path("berlin52.tsp");
+    for args in [
+        &[&berlin52[..], "--no-log"][..],
+        &[&berlin52, "--no-log", "--no-post"],
+    ] {
+        let output = run(args);
+        assert!(output.status.success(), "{:?}", output);
+        assert!(!output.stdout.is_empty(), "{:?} wrote no tour", args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.is_empty(), "{:?}: {}", args, stderr);
+    }
+}
+
+#[test]
+fn validating_a_good_tour() {
+    let berlin52 = data_path("berlin52.tsp");
+    let ids: Vec<String> = (1..=52).map(|id| id.to_string()).collect();
+    let tour = file(
+        "valid.tour",
+        &format!("TOUR_SECTION\n{}\n-1\nEOF\n", ids.join("\n")),
+    );
+    let output = run(&["validate", &berlin52, &tour]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Valid tour of 52 cities"), "{}", stdout);
+}
@@ -0,0 +1,53 @@
+// Helpers shared by the integration tests, each test binary only uses some of them
+#![allow(dead_code)]
+
+use std::process::{Command, Output};
+
+use tsp_rust::{Instance, Metric, reader};
+
+/// The path of one of the instances bundled in data/
+pub fn data_path(name: &str) -> String {
+    format!("{}/data/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// One of the instances bundled in data/
+pub fn data(name: &str) -> Instance {
+    reader::read_source(&data_path(name)).unwrap()
+}
+
+/// A few instances of every size the searches special-case, under planar and spherical
+/// metrics, including cities on top of each other
+pub fn instances() -> Vec<Instance> {
+    let berlin52 = data("berlin52.tsp");
+    let mut gr200 = Instance::from_points("gr200", data("gr666.tsp").points[..200].to_vec());
+    gr200.metric = Metric::Geo;
+    let mut instances = vec![berlin52.clone(), gr200];
+    for n in 1..=12 {
+        instances.push(Instance::from_points(
+            "small",
+            berlin52.points[..n].to_vec(),
+        ));
+    }
+    let mut repeated = berlin52.points[..20].to_vec();
+    repeated.extend_from_slice(&berlin52.points[..20]);
+    instances.push(Instance::from_points("repeated", repeated));
+    instances
+}
+
+/// Runs the tsp_rust binary with args
+pub fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tsp_rust"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Panics unless tour visits every one of the n cities exactly once, saying what built it
+pub fn assert_permutation(tour: &[usize], n: usize, what: &str) {
+    let mut seen = vec![false; n];
+    assert_eq!(tour.len(), n, "{}: tour {:?}", what, tour);
+    for &city in tour {
+        assert!(city < n && !seen[city], "{}: tour {:?}", what, tour);
+        seen[city] = true;
+    }
+}
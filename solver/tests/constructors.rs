@@ -0,0 +1,183 @@
+// Every constructor has to give a tour through all the cities exactly once, however few
+// cities there are, and each has to build the kind of tour it promises
+
+mod common;
+
+use common::{assert_permutation, data, instances};
+use tsp_rust::candidates::{CandidateKind, Candidates};
+use tsp_rust::constructor::ConstructorKind;
+use tsp_rust::tour::ArrayTour;
+use tsp_rust::{Metric, Point, Solver, SolverConfig, two_opt};
+
+const KINDS: [ConstructorKind; 4] = [
+    ConstructorKind::NearestNeighbour,
+    ConstructorKind::GreedyEdge,
+    ConstructorKind::Hilbert,
+    ConstructorKind::Christofides,
+];
+
+fn length(tour: &[usize], points: &[Point], metric: Metric) -> f64 {
+    let d = |a: usize, b: usize| metric.dist(points[a], points[b]) as f64;
+    let n = tour.len();
+    (0..n).map(|i| d(tour[i], tour[(i + 1) % n])).sum()
+}
+
+#[test]
+fn constructors() {
+    for instance in instances() {
+        let (points, metric) = (&instance.points, instance.metric);
+        for kind in KINDS {
+            let tour = kind
+                .constructor()
+                .unwrap()
+                .construct(points, metric, 0.0, 0);
+            let what = format!("{} on {} cities", kind.name(), points.len());
+            assert_permutation(&tour, points.len(), &what);
+            let noisy = kind
+                .constructor()
+                .unwrap()
+                .construct(points, metric, 0.3, 1);
+            assert_permutation(&noisy, points.len(), &format!("noisy {}", what));
+        }
+
+        // The insertion is run by the solver itself, together with every phase after it
+        for post in [false, true] {
+            let config = SolverConfig {
+                post,
+                starts: 2,
+                ..SolverConfig::default()
+            };
+            let solution = Solver::new(config).solve_instance(&instance);
+            let what = format!("insertion on {} cities, post {}", points.len(), post);
+            assert_permutation(&solution.tour, points.len(), &what);
+            assert!(
+                solution.length <= solution.construction_length * (1.0 + 1e-5),
+                "{}",
+                what
+            );
+        }
+    }
+}
+
+// The first edge greedy takes is the shortest of all, so the tour always has one of that length
+#[test]
+fn greedy_edge_takes_the_shortest_edge() {
+    for name in ["berlin52.tsp", "a280.tsp", "gr666.tsp"] {
+        let instance = data(name);
+        let (points, metric) = (&instance.points, instance.metric);
+        let n = points.len();
+        let shortest = (0..n)
+            .flat_map(|a| (a + 1..n).map(move |b| (a, b)))
+            .map(|(a, b)| metric.dist(points[a], points[b]))
+            .fold(f32::INFINITY, f32::min);
+
+        let tour = ConstructorKind::GreedyEdge
+            .constructor()
+            .unwrap()
+            .construct(points, metric, 0.0, 0);
+        let has_shortest =
+            (0..n).any(|i| metric.dist(points[tour[i]], points[tour[(i + 1) % n]]) == shortest);
+        assert!(has_shortest, "{}: no edge of length {}", name, shortest);
+    }
+}
+
+// Every step goes to the closest city left under the metric, also where that is not the
+// closest in a straight line
+#[test]
+fn nearest_neighbour_takes_the_closest_city_under_the_metric() {
+    let mut manhattan = data("berlin52.tsp");
+    manhattan.metric = Metric::Man2d;
+    for instance in [data("gr666.tsp"), manhattan] {
+        let (points, metric) = (&instance.points, instance.metric);
+        let tour = ConstructorKind::NearestNeighbour
+            .constructor()
+            .unwrap()
+            .construct(points, metric, 0.0, 0);
+        let mut visited = vec![false; points.len()];
+        for step in tour.windows(2) {
+            let (from, to) = (points[step[0]], step[1]);
+            visited[step[0]] = true;
+            let closest = (0..points.len())
+                .filter(|&city| !visited[city])
+                .map(|city| metric.dist(from, points[city]))
+                .fold(f32::INFINITY, f32::min);
+            assert_eq!(
+                metric.dist(from, points[to]),
+                closest,
+                "{:?} from {}",
+                metric,
+                step[0]
+            );
+        }
+    }
+}
+
+// A 2-opt tour is never shorter than the optimum, and Christofides stays within half again of
+// that on instances this small even with its greedy matching
+#[test]
+fn christofides_stays_close_to_two_opt() {
+    let instance = data("berlin52.tsp");
+    let (points, metric) = (&instance.points, instance.metric);
+    let christofides = ConstructorKind::Christofides
+        .constructor()
+        .unwrap()
+        .construct(points, metric, 0.0, 0);
+
+    let candidates = Candidates::build(points, metric, CandidateKind::Nearest, 8);
+    let mut two_opted = ArrayTour::new((0..points.len()).collect());
+    two_opt::two_opt(&mut two_opted, points, metric, &candidates);
+
+    let christofides = length(&christofides, points, metric);
+    let two_opted = length(two_opted.order(), points, metric);
+    assert!(
+        christofides <= 1.5 * two_opted,
+        "{} > 1.5 * {}",
+        christofides,
+        two_opted
+    );
+}
+
+// Every edge of a tour with its ends in order, so the same cycle gives the same edges
+fn edges(tour: &[usize]) -> Vec<(usize, usize)> {
+    let n = tour.len();
+    let mut edges: Vec<_> = (0..n)
+        .map(|i| {
+            let (a, b) = (tour[i], tour[(i + 1) % n]);
+            (a.min(b), a.max(b))
+        })
+        .collect();
+    edges.sort_unstable();
+    edges
+}
+
+// With noise every seed builds its own tour, and at noise 0 every seed the same one
+#[test]
+fn seeds_give_different_tours() {
+    let instance = data("pcb442.tsp");
+    let (points, metric) = (&instance.points, instance.metric);
+    for kind in KINDS {
+        let constructor = kind.constructor().unwrap();
+        let build = |noise, seed| edges(&constructor.construct(points, metric, noise, seed));
+        assert_eq!(
+            build(0.0, 1),
+            build(0.0, 2),
+            "{} without noise",
+            kind.name()
+        );
+        assert_ne!(build(0.3, 1), build(0.3, 2), "{} with noise", kind.name());
+
+        // Only the first start is free of noise, the others differ with the seed
+        let start_lengths = |seed| {
+            let config = SolverConfig {
+                constructor: kind,
+                starts: 3,
+                seed,
+                ..SolverConfig::default()
+            };
+            Solver::new(config).solve_instance(&instance).start_lengths
+        };
+        let (first, second) = (start_lengths(1), start_lengths(2));
+        assert_eq!(first[0], second[0], "{}", kind.name());
+        assert_ne!(first[1..], second[1..], "{}", kind.name());
+    }
+}
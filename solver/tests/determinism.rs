@@ -0,0 +1,111 @@
+// The seed alone decides the tour: how many threads rayon has to run the batches and starts
+// on must never change which one comes out
+
+mod common;
+
+use common::{data, data_path, run};
+use tsp_rust::candidates::CandidateKind;
+use tsp_rust::{Instance, Metric, Solver, SolverConfig};
+
+fn solve_on(threads: usize, instance: &Instance, config: SolverConfig) -> Vec<usize> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    pool.install(|| Solver::new(config).solve_instance(instance).tour)
+}
+
+fn assert_same_for_any_thread_count(instance: &Instance, config: SolverConfig) {
+    let tour = solve_on(1, instance, config);
+    for threads in [2, 4] {
+        assert_eq!(
+            solve_on(threads, instance, config),
+            tour,
+            "{} threads, {:?}",
+            threads,
+            config
+        );
+    }
+}
+
+#[test]
+fn default_pipeline() {
+    let mut gr200 = Instance::from_points("gr200", data("gr666.tsp").points[..200].to_vec());
+    gr200.metric = Metric::Geo;
+    for instance in [data("a280.tsp"), gr200] {
+        let config = SolverConfig {
+            seed: 7,
+            ..SolverConfig::default()
+        };
+        assert_same_for_any_thread_count(&instance, config);
+    }
+}
+
+#[test]
+fn every_local_search() {
+    let config = SolverConfig {
+        seed: 7,
+        two_opt: true,
+        three_opt: true,
+        lin_kernighan: true,
+        candidates: CandidateKind::Quadrant,
+        ..SolverConfig::default()
+    };
+    assert_same_for_any_thread_count(&data("a280.tsp"), config);
+}
+
+#[test]
+fn insertion_batches() {
+    for insert_batch in [1, 8, 64] {
+        let config = SolverConfig {
+            seed: 7,
+            insert_batch,
+            ..SolverConfig::default()
+        };
+        assert_same_for_any_thread_count(&data("pcb442.tsp"), config);
+    }
+}
+
+#[test]
+fn several_starts() {
+    let config = SolverConfig {
+        seed: 7,
+        starts: 4,
+        insert_batch: 4,
+        ..SolverConfig::default()
+    };
+    assert_same_for_any_thread_count(&data("pcb442.tsp"), config);
+}
+
+// The first start never has noise, every other one depends on the seed
+#[test]
+fn the_seed_matters() {
+    let pcb442 = data("pcb442.tsp");
+    let lengths = |seed: u64| {
+        let config = SolverConfig {
+            seed,
+            starts: 4,
+            ..SolverConfig::default()
+        };
+        Solver::new(config).solve_instance(&pcb442).start_lengths
+    };
+    let (one, two) = (lengths(1), lengths(2));
+    assert_eq!(one[0], two[0]);
+    assert_ne!(one[1..], two[1..]);
+}
+
+// What the regression scripts compare is the file written, so check it byte for byte
+#[test]
+fn the_written_tour_is_byte_identical() {
+    let berlin52 = data_path("berlin52.tsp");
+    let solve = |threads: &str| {
+        let output = run(&[&berlin52, "--no-log", "--seed", "3", "--threads", threads]);
+        assert!(output.status.success(), "{:?}", output);
+        output.stdout
+    };
+    let written = solve("1");
+    assert!(!written.is_empty());
+    for threads in ["2", "4"] {
+        assert!(solve(threads) == written, "{} threads", threads);
+    }
+}
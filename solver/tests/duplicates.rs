@@ -0,0 +1,104 @@
+// Coincident cities used to be merged or dropped because the solver told cities apart by
+// their coordinates, every one of them has to come back in the tour
+
+mod common;
+
+use common::assert_permutation;
+use tsp_rust::{Metric, Point, Solver, SolverConfig, reader};
+
+fn p(x: f32, y: f32) -> Point {
+    Point { x, y }
+}
+
+fn grid(side: usize) -> Vec<Point> {
+    (0..side * side)
+        .map(|i| p((i % side) as f32 * 10.0, (i / side) as f32 * 7.0))
+        .collect()
+}
+
+#[test]
+fn duplicates_inside_and_on_the_hull_are_all_visited() {
+    let mut points = grid(6);
+    // Copies of hull corners, interior points and a triple
+    points.extend([
+        p(0.0, 0.0),
+        p(50.0, 35.0),
+        p(20.0, 14.0),
+        p(20.0, 14.0),
+        p(30.0, 21.0),
+    ]);
+
+    for config in [
+        SolverConfig::default(),
+        SolverConfig {
+            post: false,
+            ..SolverConfig::default()
+        },
+    ] {
+        let solution = Solver::new(config).solve(&points);
+        assert_permutation(&solution.tour, points.len(), &format!("{:?}", config));
+
+        // Coincident cities add nothing to the tour
+        let unique = Solver::new(config).solve(&grid(6));
+        assert_eq!(solution.length, unique.length);
+        assert_eq!(solution.tsplib_length, unique.tsplib_length);
+    }
+}
+
+#[test]
+fn copies_of_a_city_are_visited_one_after_another() {
+    let mut points = grid(5);
+    points.push(points[7]);
+    points.push(points[7]);
+    let solution = Solver::new(SolverConfig::default()).solve(&points);
+    assert_permutation(&solution.tour, points.len(), "copies");
+
+    let at = solution.tour.iter().position(|&i| i == 7).unwrap();
+    let mut run: Vec<usize> = solution.tour[at..at + 3].to_vec();
+    run.sort_unstable();
+    assert_eq!(run, vec![7, 25, 26]);
+}
+
+#[test]
+fn every_city_at_one_place() {
+    let points = vec![p(3.0, 4.0); 9];
+    let solution = Solver::new(SolverConfig::default()).solve(&points);
+    assert_permutation(&solution.tour, points.len(), "one place");
+    assert_eq!(solution.length, 0.0);
+}
+
+#[test]
+fn negative_zero_is_the_same_place_as_zero() {
+    let mut points = grid(4);
+    points.push(p(-0.0, 0.0));
+    points.push(p(0.0, -0.0));
+    let solution = Solver::new(SolverConfig::default()).solve(&points);
+    assert_permutation(&solution.tour, points.len(), "negative zero");
+}
+
+#[test]
+fn duplicate_cities_in_a_tsplib_file() {
+    let text = "NAME : dups
+TYPE : TSP
+DIMENSION : 8
+EDGE_WEIGHT_TYPE : ATT
+NODE_COORD_SECTION
+1 0 0
+2 100 0
+3 100 100
+4 0 100
+5 50 50
+6 50 50
+7 100 0
+8 25 75
+EOF
+";
+    let instance = reader::parse_file(text).unwrap();
+    assert_eq!(instance.metric, Metric::Att);
+    let solution = Solver::new(SolverConfig::default()).solve_instance(&instance);
+    assert_permutation(&solution.tour, 8, "tsplib file");
+    assert_eq!(
+        solution.tsplib_length,
+        instance.tsplib_tour_length(&solution.tour)
+    );
+}
@@ -0,0 +1,151 @@
+// Every EDGE_WEIGHT_FORMAT is only a different order to write the same matrix in, so all of
+// them have to read back to exactly the distances they were written from
+
+use tsp_rust::{Solver, SolverConfig, reader};
+
+const N: usize = 6;
+
+// A symmetric matrix with a different distance for every pair, so reading any number into
+// the wrong place shows
+fn distance(i: usize, j: usize) -> usize {
+    if i == j {
+        0
+    } else {
+        10 * i.min(j) + i.max(j) + 7
+    }
+}
+
+// The numbers of an EDGE_WEIGHT_SECTION in the given layout, one row or column per line
+fn weights(format: &str) -> String {
+    let (upper, diagonal, by_column) = match format {
+        "FULL_MATRIX" => return full_matrix(),
+        "UPPER_ROW" => (true, false, false),
+        "LOWER_ROW" => (false, false, false),
+        "UPPER_DIAG_ROW" => (true, true, false),
+        "LOWER_DIAG_ROW" => (false, true, false),
+        "UPPER_COL" => (true, false, true),
+        "LOWER_COL" => (false, false, true),
+        "UPPER_DIAG_COL" => (true, true, true),
+        "LOWER_DIAG_COL" => (false, true, true),
+        _ => unreachable!(),
+    };
+    let mut text = String::new();
+    for outer in 0..N {
+        let mut line = Vec::new();
+        for inner in 0..N {
+            let (row, col) = if by_column {
+                (inner, outer)
+            } else {
+                (outer, inner)
+            };
+            let keep = if upper { col > row } else { col < row };
+            if keep || (diagonal && row == col) {
+                line.push(distance(row, col).to_string());
+            }
+        }
+        if !line.is_empty() {
+            text += &line.join(" ");
+            text += "\n";
+        }
+    }
+    text
+}
+
+fn full_matrix() -> String {
+    let rows: Vec<String> = (0..N)
+        .map(|i| {
+            let row: Vec<String> = (0..N).map(|j| distance(i, j).to_string()).collect();
+            row.join(" ") + "\n"
+        })
+        .collect();
+    rows.concat()
+}
+
+fn explicit(format: &str, weights: &str) -> String {
+    format!(
+        "NAME : explicit
+TYPE : TSP
+DIMENSION : {}
+EDGE_WEIGHT_TYPE : EXPLICIT
+EDGE_WEIGHT_FORMAT : {}
+EDGE_WEIGHT_SECTION
+{}EOF
+",
+        N, format, weights
+    )
+}
+
+fn check_matrix(text: &str, format: &str) {
+    let instance = reader::parse_file(text).unwrap();
+    let matrix = instance.matrix.as_ref().expect(format);
+    assert_eq!(matrix.len(), N, "{}", format);
+    for i in 0..N {
+        for j in 0..N {
+            let expected = distance(i, j) as f32;
+            assert_eq!(matrix.dist(i, j), expected, "{:?}", (format, i, j));
+        }
+    }
+    let length = matrix.tsplib_tour_length(&[0, 2, 4, 1, 3, 5]);
+    assert_eq!(length, 135, "{}", format);
+}
+
+#[test]
+fn every_format_reads_the_same_matrix() {
+    let formats = [
+        "FULL_MATRIX",
+        "UPPER_ROW",
+        "LOWER_ROW",
+        "UPPER_DIAG_ROW",
+        "LOWER_DIAG_ROW",
+        "UPPER_COL",
+        "LOWER_COL",
+        "UPPER_DIAG_COL",
+        "LOWER_DIAG_COL",
+    ];
+    for format in formats {
+        check_matrix(&explicit(format, &weights(format)), format);
+    }
+}
+
+// Writers wrap the numbers of a section wherever they like, not only at the end of a row
+#[test]
+fn line_breaks_inside_the_section_do_not_matter() {
+    for format in ["FULL_MATRIX", "LOWER_DIAG_ROW", "UPPER_COL"] {
+        let numbers: Vec<String> = weights(format)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        for per_line in [1, 4, numbers.len()] {
+            let wrapped: Vec<String> = numbers
+                .chunks(per_line)
+                .map(|c| c.join(" ") + "\n")
+                .collect();
+            check_matrix(&explicit(format, &wrapped.concat()), format);
+        }
+    }
+}
+
+// The matrix goes through the same 2-opt and or-opt as coordinates, with candidates from its
+// rows, and the tour they leave is never longer than the nearest neighbour one
+#[test]
+fn local_searches_use_the_matrix() {
+    let instance = reader::parse_file(&explicit("FULL_MATRIX", &full_matrix())).unwrap();
+    let matrix = instance.matrix.as_ref().unwrap();
+    for (edge_swap, or_opt) in [(true, false), (false, true), (true, true)] {
+        let config = SolverConfig {
+            edge_swap,
+            or_opt,
+            neighbours: 3,
+            ..SolverConfig::default()
+        };
+        let solution = Solver::new(config).solve_matrix(matrix);
+        let mut cities = solution.tour.clone();
+        cities.sort();
+        assert_eq!(cities, (0..N).collect::<Vec<_>>());
+        assert_eq!(
+            solution.tsplib_length,
+            matrix.tsplib_tour_length(&solution.tour)
+        );
+        assert!(solution.length <= solution.construction_length);
+    }
+}
@@ -0,0 +1,258 @@
+// The priority queue only saves work: construction has to insert exactly the points, in
+// exactly the order, that scoring every hull edge again for every point would
+
+#![feature(portable_simd)]
+
+mod common;
+
+use indicatif::ProgressBar;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use common::{assert_permutation, data, instances};
+use tsp_rust::criterion::CriterionKind;
+use tsp_rust::insertion;
+use tsp_rust::precompute::SpatialGrid;
+use tsp_rust::relp::InsertPointResult;
+use tsp_rust::shared::{self, SimdF32};
+use tsp_rust::tour::Tour;
+use tsp_rust::{Metric, Point, Solver, SolverConfig, math};
+
+const CRITERIA: [CriterionKind; 4] = [
+    CriterionKind::Lda,
+    CriterionKind::Cheapest,
+    CriterionKind::Farthest,
+    CriterionKind::AngleCheapest,
+];
+
+// The convex hull and a grid of the cities inside it, as the solver starts construction
+fn start(points: &[Point]) -> (Tour, SpatialGrid<'_>, usize) {
+    let n = points.len();
+    let hull_order = math::convex_hull_indices(points);
+    let hull = Tour::from_order(n, &hull_order);
+    let all: Vec<usize> = (0..n).collect();
+    let mut grid = SpatialGrid::new(points, &all);
+    for &city in &hull_order {
+        grid.remove_point(city);
+    }
+    let adaptive_n = 64.min(grid.len() / 10).max(8);
+    (hull, grid, adaptive_n)
+}
+
+fn search_radius(hull: &Tour, points: &[Point]) -> f32 {
+    let mut total = 0.0;
+    for &city in hull.cities() {
+        let (a, b) = (points[city], points[hull.next(city)]);
+        total += ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+    }
+    total / hull.len() as f32 * 2.0
+}
+
+// The best insertion over every hull edge scored from scratch, equal scores going to the edge
+// starting at the lowest city, if any has a positive score
+fn best_insertion(
+    hull: &Tour,
+    points: &[Point],
+    grid: &SpatialGrid,
+    n: usize,
+    metric: Metric,
+    criterion: CriterionKind,
+) -> Option<InsertPointResult> {
+    let radius = search_radius(hull, points);
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut best = InsertPointResult {
+        lda: 0.0,
+        best_a: usize::MAX,
+        best_c: usize::MAX,
+    };
+    for &a_city in hull.cities() {
+        let (a, b) = (points[a_city], points[hull.next(a_city)]);
+        let candidates = grid.query_edge_candidates(a, b, radius);
+        let mut edge_best = (f32::NEG_INFINITY, usize::MAX);
+        for &c_city in candidates.iter().take(n.min(32)) {
+            let c = points[c_city];
+            let score = criterion.criterion().score(
+                SimdF32::splat(a.x),
+                SimdF32::splat(a.y),
+                SimdF32::splat(b.x),
+                SimdF32::splat(b.y),
+                SimdF32::splat(c.x),
+                SimdF32::splat(c.y),
+                &mut rng,
+                0.0,
+                metric,
+            )[0];
+            if score > edge_best.0 {
+                edge_best = (score, c_city);
+            }
+        }
+        let (score, c_city) = edge_best;
+        if score > best.lda || (score == best.lda && score > 0.0 && a_city < best.best_a) {
+            best = InsertPointResult {
+                lda: score,
+                best_a: a_city,
+                best_c: c_city,
+            };
+        }
+    }
+    (best.lda > 0.0).then_some(best)
+}
+
+// The inner city nearest each hull city in a straight line, the pair closest under the metric
+fn closest_pair(
+    hull: &Tour,
+    points: &[Point],
+    grid: &SpatialGrid,
+    metric: Metric,
+) -> (usize, usize) {
+    let mut best = (f32::INFINITY, usize::MAX, usize::MAX);
+    for &hull_city in hull.cities() {
+        let inner_city = grid.nearest(points[hull_city]).unwrap();
+        let distance = metric.dist(points[inner_city], points[hull_city]);
+        if distance < best.0 {
+            best = (distance, hull_city, inner_city);
+        }
+    }
+    (best.1, best.2)
+}
+
+// Construction with every hull edge scored again for every point
+fn rescan(points: &[Point], metric: Metric, criterion: CriterionKind) -> Vec<usize> {
+    let (mut hull, mut grid, n) = start(points);
+    while !grid.is_empty() {
+        let (a, c) = match best_insertion(&hull, points, &grid, n, metric, criterion) {
+            Some(result) => (result.best_a, result.best_c),
+            None => closest_pair(&hull, points, &grid, metric),
+        };
+        grid.remove_point(c);
+        hull.insert_after(a, c);
+    }
+    hull.order()
+}
+
+fn queued(points: &[Point], metric: Metric, batch: usize, criterion: CriterionKind) -> Vec<usize> {
+    let (mut hull, mut grid, n) = start(points);
+    let mut insert_log = Vec::new();
+    insertion::insert_all(
+        &mut hull,
+        points,
+        &mut grid,
+        &mut insert_log,
+        n,
+        batch,
+        criterion.criterion(),
+        0.0,
+        metric,
+        shared::mix_seed(7, 0),
+        &ProgressBar::hidden(),
+    );
+    hull.order()
+}
+
+#[test]
+fn the_queue_inserts_like_a_full_rescan() {
+    let names = [
+        "berlin52.tsp",
+        "chase_test.tsp",
+        "a280.tsp",
+        "pcb442.tsp",
+        "gr666.tsp",
+        "d657.tsp",
+        "p654.tsp",
+    ];
+    for name in names {
+        let instance = data(name);
+        let (points, metric) = (&instance.points, instance.metric);
+        let lda = CriterionKind::Lda;
+        assert_eq!(
+            queued(points, metric, 1, lda),
+            rescan(points, metric, lda),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn every_criterion_inserts_like_a_full_rescan() {
+    for instance in instances() {
+        let (points, metric) = (&instance.points, instance.metric);
+        if points.len() < 4 {
+            continue;
+        }
+        for criterion in CRITERIA {
+            assert_eq!(
+                queued(points, metric, 1, criterion),
+                rescan(points, metric, criterion),
+                "{} {:?}",
+                instance.name,
+                criterion
+            );
+        }
+    }
+}
+
+#[test]
+fn batches_insert_every_city() {
+    for instance in instances() {
+        let (points, metric) = (&instance.points, instance.metric);
+        if points.len() < 4 {
+            continue;
+        }
+        for batch in [4, 64] {
+            let what = format!("batch {} on {}", batch, instance.name);
+            let tour = queued(points, metric, batch, CriterionKind::Lda);
+            assert_permutation(&tour, points.len(), &what);
+
+            let config = SolverConfig {
+                insert_batch: batch,
+                ..SolverConfig::default()
+            };
+            let solution = Solver::new(config).solve_instance(&instance);
+            assert_permutation(&solution.tour, points.len(), &what);
+            let construction = solution.construction_length;
+            assert!(
+                solution.length <= construction + 1e-4 * construction,
+                "{}: {} > {}",
+                what,
+                solution.length,
+                construction
+            );
+        }
+    }
+}
+
+#[test]
+fn every_criterion_gives_a_tour() {
+    for instance in instances() {
+        for criterion in CRITERIA {
+            let config = SolverConfig {
+                criterion,
+                ..SolverConfig::default()
+            };
+            let solution = Solver::new(config).solve_instance(&instance);
+            let what = format!("{:?} on {}", criterion, instance.name);
+            assert_permutation(&solution.tour, instance.points.len(), &what);
+        }
+    }
+}
+
+#[test]
+fn several_starts_keep_the_shortest() {
+    for instance in instances() {
+        // Three cities or fewer only have the one tour
+        if instance.points.len() < 4 {
+            continue;
+        }
+        let config = SolverConfig {
+            starts: 4,
+            ..SolverConfig::default()
+        };
+        let solution = Solver::new(config).solve_instance(&instance);
+        let what = format!("4 starts on {}", instance.name);
+        assert_permutation(&solution.tour, instance.points.len(), &what);
+        assert_eq!(solution.start_lengths.len(), 4, "{}", what);
+        let shortest = *solution.start_lengths.iter().min().unwrap();
+        assert_eq!(solution.tsplib_length, shortest, "{}", what);
+    }
+}
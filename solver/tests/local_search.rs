@@ -0,0 +1,196 @@
+// Every search that improves a tour has to hand back a tour through the same cities that is
+// never longer than it was given, whatever state the tour was in and however few cities
+// there are
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+use common::{assert_permutation, instances};
+use tsp_rust::candidates::{CandidateKind, Candidates};
+use tsp_rust::tour::{ArrayTour, Tour};
+use tsp_rust::{Metric, Point, edges, ils, lin_kernighan, or_opt, three_opt, two_opt};
+
+fn length(tour: &[usize], points: &[Point], metric: Metric) -> f64 {
+    let d = |a: usize, b: usize| metric.dist(points[a], points[b]) as f64;
+    let n = tour.len();
+    (0..n).map(|i| d(tour[i], tour[(i + 1) % n])).sum()
+}
+
+// The tours the searches start from, a random one leaves them the most to do
+fn start_tours(n: usize) -> Vec<Vec<usize>> {
+    let mut shuffled: Vec<usize> = (0..n).collect();
+    shuffled.shuffle(&mut StdRng::seed_from_u64(n as u64));
+    vec![(0..n).collect(), shuffled]
+}
+
+// Runs search on every start tour of every instance and checks what it hands back, including
+// the gain it reports if it reports one
+fn check_search(
+    name: &str,
+    search: impl Fn(&mut ArrayTour, &[Point], Metric, &Candidates) -> Option<f64>,
+) {
+    for instance in instances() {
+        let (points, metric) = (&instance.points, instance.metric);
+        let candidates = Candidates::build(points, metric, CandidateKind::Nearest, 8);
+        for start in start_tours(points.len()) {
+            let what = format!("{} on {} cities of {}", name, points.len(), instance.name);
+            let before = length(&start, points, metric);
+            let mut tour = ArrayTour::new(start);
+            let gain = search(&mut tour, points, metric, &candidates);
+            assert_permutation(tour.order(), points.len(), &what);
+            let after = length(tour.order(), points, metric);
+            if let Some(gain) = gain {
+                assert!(
+                    (before - after - gain).abs() <= 1e-6 * before.max(1.0),
+                    "{}: gained {} but reported {}",
+                    what,
+                    before - after,
+                    gain
+                );
+            }
+            assert!(
+                after <= before + 1e-3 * before.max(1.0),
+                "{}: {} > {}",
+                what,
+                after,
+                before
+            );
+            // A shuffled tour of berlin52 leaves every search something to do
+            if instance.name == "berlin52" {
+                assert!(after < before, "{} did not improve", what);
+            }
+        }
+    }
+}
+
+#[test]
+fn two_opt() {
+    check_search("2-opt", |tour, points, metric, candidates| {
+        Some(two_opt::two_opt(tour, points, metric, candidates))
+    });
+}
+
+#[test]
+fn three_opt() {
+    check_search("3-opt", |tour, points, metric, candidates| {
+        Some(three_opt::three_opt(tour, points, metric, candidates))
+    });
+}
+
+#[test]
+fn lin_kernighan() {
+    check_search("Lin-Kernighan", |tour, points, metric, candidates| {
+        Some(lin_kernighan::lin_kernighan(
+            tour, points, metric, candidates, None,
+        ))
+    });
+}
+
+// Kicks that the repair leaves a rounding error longer are kept, but the tour handed back
+// is the shortest one seen, so it can only get shorter than the one 2-opt gave
+#[test]
+fn iterated_local_search() {
+    check_search(
+        "iterated local search",
+        |tour, points, metric, candidates| {
+            two_opt::two_opt(tour, points, metric, candidates);
+            let start = length(tour.order(), points, metric);
+            let deadline = Instant::now() + Duration::from_millis(20);
+            ils::iterated_local_search(tour, points, metric, deadline, 7, |tour, changed| {
+                two_opt::two_opt_from(tour, points, metric, candidates, changed)
+            });
+            let end = length(tour.order(), points, metric);
+            // Equal tours can still add up to a rounding error apart
+            assert!(end <= start + 1e-9 * start, "{} > {}", end, start);
+            None
+        },
+    );
+}
+
+#[test]
+fn or_opt() {
+    check_search("or-opt", |tour, points, metric, candidates| {
+        let mut linked = Tour::from_order(points.len(), tour.order());
+        or_opt::or_opt(&mut linked, points, metric, candidates);
+        // It stops at a local optimum, so running it again finds nothing to move
+        assert!(!or_opt::or_opt(&mut linked, points, metric, candidates));
+        *tour = ArrayTour::new(linked.order());
+        None
+    });
+}
+
+// n cities evenly around a circle, where going round in order is the only optimal tour
+fn circle(n: usize) -> Vec<Point> {
+    (0..n)
+        .map(|i| {
+            let angle = i as f32 / n as f32 * std::f32::consts::TAU;
+            Point {
+                x: 1000.0 * angle.cos(),
+                y: 1000.0 * angle.sin(),
+            }
+        })
+        .collect()
+}
+
+fn is_round_the_circle(tour: &[usize]) -> bool {
+    let n = tour.len();
+    let at = tour.iter().position(|&city| city == 0).unwrap();
+    let forward = (0..n).all(|i| tour[(at + i) % n] == i);
+    let backward = (0..n).all(|i| tour[(at + n - i) % n] == i);
+    forward || backward
+}
+
+#[test]
+fn or_opt_puts_segments_back_either_way_round() {
+    let points = circle(20);
+    let candidates = Candidates::build(&points, Metric::Euc2d, CandidateKind::Nearest, 8);
+    // 10 11 12 sits between 3 and 4, once as it is and once reversed, so putting it back
+    // between 9 and 13 takes a move of each kind
+    let forward = [
+        0, 1, 2, 3, 10, 11, 12, 4, 5, 6, 7, 8, 9, 13, 14, 15, 16, 17, 18, 19,
+    ];
+    let reversed = [
+        0, 1, 2, 3, 12, 11, 10, 4, 5, 6, 7, 8, 9, 13, 14, 15, 16, 17, 18, 19,
+    ];
+    for order in [forward, reversed] {
+        let mut tour = Tour::from_order(points.len(), &order);
+        assert!(or_opt::or_opt(
+            &mut tour,
+            &points,
+            Metric::Euc2d,
+            &candidates
+        ));
+        assert!(is_round_the_circle(&tour.order()), "{:?}", tour.order());
+    }
+}
+
+#[test]
+fn crossing_elimination() {
+    check_search("crossing elimination", |tour, points, metric, _| {
+        edges::eliminate_all_crossings(tour, points, metric);
+        None
+    });
+}
+
+// The smallest tour 2-opt can improve: the corners of a square visited across both diagonals
+#[test]
+fn two_opt_uncrosses_a_square() {
+    let points = circle(4);
+    let candidates = Candidates::build(&points, Metric::Euc2d, CandidateKind::Nearest, 8);
+    let crossed = [0, 2, 1, 3];
+    let mut tour = ArrayTour::new(crossed.to_vec());
+    let gain = two_opt::two_opt(&mut tour, &points, Metric::Euc2d, &candidates);
+    assert!(is_round_the_circle(tour.order()), "{:?}", tour.order());
+    let expected =
+        length(&crossed, &points, Metric::Euc2d) - length(tour.order(), &points, Metric::Euc2d);
+    assert!(
+        gain > 0.0 && (gain - expected).abs() < 1e-6 * expected,
+        "{}",
+        gain
+    );
+}
@@ -0,0 +1,113 @@
+// Every EDGE_WEIGHT_TYPE has to round its distances exactly the way TSPLIB does, or the
+// lengths printed for its instances cannot be compared with the published ones
+
+mod common;
+
+use common::data;
+use tsp_rust::{Instance, Metric, Point, reader};
+
+fn instance(metric: &str, coords: &str) -> Instance {
+    let text = format!(
+        "NAME : test
+TYPE : TSP
+EDGE_WEIGHT_TYPE : {}
+NODE_COORD_SECTION
+{}EOF
+",
+        metric, coords
+    );
+    reader::parse_file(&text).unwrap()
+}
+
+// The length TSPLIB gives the tour through the node numbers ids
+fn tour_length(instance: &Instance, ids: &[usize]) -> i64 {
+    let point = |id: usize| {
+        let at = instance
+            .node_ids
+            .iter()
+            .position(|&node| node == id)
+            .unwrap();
+        instance.points[at]
+    };
+    (0..ids.len())
+        .map(|i| {
+            let next = ids[(i + 1) % ids.len()];
+            instance.metric.tsplib_dist(point(ids[i]), point(next))
+        })
+        .sum()
+}
+
+fn p(x: f32, y: f32) -> Point {
+    Point { x, y }
+}
+
+#[test]
+fn att48_optimal_tour() {
+    let att48 = instance(
+        "ATT",
+        "1 6734 1453\n2 2233 10\n3 5530 1424\n4 401 841\n5 3082 1644\n6 7608 4458\n\
+         7 7573 3716\n8 7265 1268\n9 6898 1885\n10 1112 2049\n11 5468 2606\n12 5989 2873\n\
+         13 4706 2674\n14 4612 2035\n15 6347 2683\n16 6107 669\n17 7611 5184\n18 7462 3590\n\
+         19 7732 4723\n20 5900 3561\n21 4483 3369\n22 6101 1110\n23 5199 2182\n24 1633 2809\n\
+         25 4307 2322\n26 675 1006\n27 7555 4819\n28 7541 3981\n29 3177 756\n30 7352 4506\n\
+         31 7545 2801\n32 3245 3305\n33 6426 3173\n34 4608 1198\n35 23 2216\n36 7248 3779\n\
+         37 7762 4595\n38 7392 2244\n39 3484 2829\n40 6271 2135\n41 4985 140\n42 1916 1569\n\
+         43 7280 4899\n44 7509 3239\n45 10 2676\n46 6807 2993\n47 5185 3258\n48 3023 1942\n",
+    );
+    let optimal = [
+        1, 8, 38, 31, 44, 18, 7, 28, 6, 37, 19, 27, 17, 43, 30, 36, 46, 33, 20, 47, 21, 32, 39, 48,
+        5, 42, 24, 10, 45, 35, 4, 26, 2, 29, 34, 41, 16, 22, 3, 23, 14, 25, 13, 11, 12, 15, 40, 9,
+    ];
+    assert_eq!(tour_length(&att48, &optimal), 10628);
+}
+
+#[test]
+fn ulysses16_optimal_tour() {
+    let ulysses16 = instance(
+        "GEO",
+        "1 38.24 20.42\n2 39.57 26.15\n3 40.56 25.32\n4 36.26 23.12\n5 33.48 10.54\n\
+         6 37.56 12.19\n7 38.42 13.11\n8 37.52 20.44\n9 41.23 9.10\n10 41.17 13.05\n\
+         11 36.08 -5.21\n12 38.47 15.13\n13 38.15 15.35\n14 37.51 15.17\n15 35.49 14.32\n\
+         16 39.36 19.56\n",
+    );
+    let optimal = [1, 14, 13, 12, 7, 6, 15, 5, 11, 9, 10, 16, 3, 2, 4, 8];
+    assert_eq!(tour_length(&ulysses16, &optimal), 6859);
+}
+
+// The TSPLIB FAQ gives the length of the tour 1, 2, ..., n of these to check a distance
+// function against
+#[test]
+fn canonical_tours() {
+    for (name, length) in [("pcb442.tsp", 221440), ("gr666.tsp", 423710)] {
+        let instance = data(name);
+        assert_eq!(
+            tour_length(&instance, &instance.node_ids),
+            length,
+            "{}",
+            name
+        );
+    }
+}
+
+// CEIL_2D rounds every distance up and MAN_2D rounds the sum of both sides to the nearest
+#[test]
+fn ceil_2d_and_man_2d_rounding() {
+    let cases = [
+        (Metric::Ceil2d, p(0.0, 0.0), p(3.0, 4.0), 5),
+        (Metric::Ceil2d, p(0.0, 0.0), p(1.0, 1.0), 2),
+        (Metric::Ceil2d, p(0.0, 0.0), p(10.0, 0.1), 11),
+        (Metric::Man2d, p(0.0, 0.0), p(3.0, 4.0), 7),
+        (Metric::Man2d, p(1.0, 1.0), p(-1.2, 2.1), 3),
+        (Metric::Man2d, p(0.0, 0.0), p(0.4, 0.3), 1),
+        (Metric::Man2d, p(0.0, 0.0), p(0.2, 0.2), 0),
+    ];
+    for (metric, a, b, expected) in cases {
+        assert_eq!(metric.tsplib_dist(a, b), expected, "{:?}", (metric, a, b));
+        assert_eq!(metric.tsplib_dist(b, a), expected, "{:?}", (metric, b, a));
+    }
+
+    let square = instance("CEIL_2D", "1 0 0\n2 1.5 0\n3 1.5 1.5\n4 0 1.5\n");
+    assert_eq!(tour_length(&square, &[1, 2, 3, 4]), 8);
+    let square = instance("MAN_2D", "1 0 0\n2 1.5 0\n3 1.5 1.5\n4 0 1.5\n");
+    assert_eq!(tour_length(&square, &[1, 3, 2, 4]), 10);
+}
@@ -0,0 +1,195 @@
+// Bad input has to come back as a ParseError naming the line, section and token, never as a
+// panic further down in the solver
+
+use tsp_rust::error::{EXIT_MALFORMED, EXIT_UNSUPPORTED, ParseError, ParseErrorKind};
+use tsp_rust::reader;
+
+fn tsplib(coords: &str) -> String {
+    format!(
+        "NAME : bad
+TYPE : TSP
+EDGE_WEIGHT_TYPE : EUC_2D
+NODE_COORD_SECTION
+{}EOF
+",
+        coords
+    )
+}
+
+fn parse_error(result: Result<tsp_rust::Instance, ParseError>) -> ParseError {
+    match result {
+        Ok(instance) => panic!("parsed {} cities", instance.len()),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn repeated_node_id_in_a_tsplib_file() {
+    let err = parse_error(reader::parse_file(&tsplib("1 0 0\n2 5 5\n3 9 1\n2 3 3\n")));
+    assert_eq!(err.line, 8);
+    assert_eq!(err.section, "NODE_COORD_SECTION");
+    assert_eq!(err.token, "2");
+    assert_eq!(err.kind, ParseErrorKind::RepeatedNodeId { first_line: 6 });
+    assert_eq!(err.exit_code(), EXIT_MALFORMED);
+    assert_eq!(
+        err.to_string(),
+        "line 8 (NODE_COORD_SECTION): node 2 was already given on line 6"
+    );
+}
+
+#[test]
+fn repeated_node_id_in_a_point_list() {
+    let err = parse_error(reader::parse_point_list("1 0 0\n2 5 5\n1 3 3\n"));
+    assert_eq!(err.line, 3);
+    assert_eq!(err.kind, ParseErrorKind::RepeatedNodeId { first_line: 1 });
+}
+
+#[test]
+fn coordinates_that_are_not_finite() {
+    for token in ["nan", "NaN", "inf", "-inf", "infinity", "1e39"] {
+        let coords = format!("1 0 0\n2 5 5\n3 1 {}\n", token);
+        let err = parse_error(reader::parse_file(&tsplib(&coords)));
+        assert_eq!(err.line, 7, "{}", token);
+        assert_eq!(err.section, "NODE_COORD_SECTION");
+        assert_eq!(err.token, token);
+        assert_eq!(err.kind, ParseErrorKind::ExpectedNumber);
+        assert_eq!(err.exit_code(), EXIT_MALFORMED);
+    }
+}
+
+#[test]
+fn numbers_that_are_not_finite_outside_node_coords() {
+    let err = parse_error(reader::parse_point_array("[0, 0, nan, 1, 2, 3]"));
+    assert_eq!((err.section, err.token.as_str()), ("point array", "nan"));
+    assert_eq!(err.kind, ParseErrorKind::ExpectedNumber);
+
+    let err = parse_error(reader::parse_point_list("0 0\n1 inf\n"));
+    assert_eq!((err.line, err.section), (2, "point list"));
+    assert_eq!(err.kind, ParseErrorKind::ExpectedNumber);
+
+    let explicit = "NAME : bad
+TYPE : TSP
+DIMENSION : 3
+EDGE_WEIGHT_TYPE : EXPLICIT
+EDGE_WEIGHT_FORMAT : UPPER_ROW
+EDGE_WEIGHT_SECTION
+1 2
+infinity
+EOF
+";
+    let err = parse_error(reader::parse_file(explicit));
+    assert_eq!((err.line, err.section), (8, "EDGE_WEIGHT_SECTION"));
+    assert_eq!(err.kind, ParseErrorKind::ExpectedNumber);
+    assert_eq!(
+        err.to_string(),
+        "line 8 (EDGE_WEIGHT_SECTION): expected a number, got 'infinity'"
+    );
+}
+
+#[test]
+fn malformed_and_unsupported_lines() {
+    let err = parse_error(reader::parse_file(&tsplib("1 0 0\n2 x 5\n")));
+    assert_eq!((err.line, err.token.as_str()), (6, "x"));
+    assert_eq!(err.kind, ParseErrorKind::ExpectedNumber);
+
+    let err = parse_error(reader::parse_file(&tsplib("1 0 0\n2 5\n")));
+    assert_eq!(err.kind, ParseErrorKind::MissingColumns { expected: 3 });
+    assert_eq!(err.exit_code(), EXIT_MALFORMED);
+
+    let err = parse_error(reader::parse_file(
+        "EDGE_WEIGHT_TYPE : XRAY1\nNODE_COORD_SECTION\n1 0 0\nEOF\n",
+    ));
+    assert_eq!((err.line, err.section), (1, "header"));
+    assert_eq!(
+        err.kind,
+        ParseErrorKind::Unsupported {
+            key: "EDGE_WEIGHT_TYPE"
+        }
+    );
+    assert_eq!(err.exit_code(), EXIT_UNSUPPORTED);
+
+    let err = parse_error(reader::parse_point_array("[1, 2, 3]"));
+    assert_eq!(err.kind, ParseErrorKind::OddPointCount { found: 3 });
+}
+
+// Only symmetric instances can be solved, whether the file says so or only its matrix does
+#[test]
+fn asymmetric_matrices() {
+    let atsp = "NAME : one_way
+TYPE : ATSP
+DIMENSION : 3
+EDGE_WEIGHT_TYPE : EXPLICIT
+EDGE_WEIGHT_FORMAT : FULL_MATRIX
+EDGE_WEIGHT_SECTION
+0 1 2
+1 0 3
+2 3 0
+EOF
+";
+    let err = parse_error(reader::parse_file(atsp));
+    assert_eq!((err.line, err.token.as_str()), (2, "ATSP"));
+    assert_eq!(err.kind, ParseErrorKind::Unsupported { key: "TYPE" });
+    assert_eq!(err.exit_code(), EXIT_UNSUPPORTED);
+
+    let asymmetric = atsp
+        .replace("TYPE : ATSP", "TYPE : TSP")
+        .replace("2 3 0", "2 4 0");
+    let err = parse_error(reader::parse_file(&asymmetric));
+    assert_eq!((err.line, err.section), (9, "EDGE_WEIGHT_SECTION"));
+    assert_eq!(
+        err.kind,
+        ParseErrorKind::AsymmetricWeight { row: 3, col: 2 }
+    );
+    assert_eq!(err.exit_code(), EXIT_MALFORMED);
+    assert_eq!(
+        err.to_string(),
+        "line 9 (EDGE_WEIGHT_SECTION): weight 4 from node 3 to 2 differs from the weight back, \
+         only symmetric matrices are supported"
+    );
+}
+
+// A section cut short or run into the next file shows as a city count DIMENSION disagrees with
+#[test]
+fn node_coords_that_do_not_match_dimension() {
+    let with_dimension =
+        |coords: &str| tsplib(coords).replace("TYPE : TSP\n", "TYPE : TSP\nDIMENSION : 3\n");
+    assert_eq!(
+        reader::parse_file(&with_dimension("1 0 0\n2 5 5\n3 9 1\n"))
+            .unwrap()
+            .len(),
+        3
+    );
+
+    let err = parse_error(reader::parse_file(&with_dimension("1 0 0\n2 5 5\n")));
+    assert_eq!(
+        (err.line, err.section, err.token.as_str()),
+        (3, "header", "3")
+    );
+    assert_eq!(
+        err.kind,
+        ParseErrorKind::DimensionMismatch {
+            dimension: 3,
+            found: 2
+        }
+    );
+    assert_eq!(err.exit_code(), EXIT_MALFORMED);
+    assert_eq!(
+        err.to_string(),
+        "line 3 (header): DIMENSION is 3 but NODE_COORD_SECTION has 2 cities"
+    );
+
+    let err = parse_error(reader::parse_file(&with_dimension(
+        "1 0 0\n2 5 5\n3 9 1\n4 3 3\n5 1 1\n",
+    )));
+    assert_eq!(
+        (err.line, err.section, err.token.as_str()),
+        (9, "NODE_COORD_SECTION", "4 3 3")
+    );
+    assert_eq!(
+        err.kind,
+        ParseErrorKind::DimensionMismatch {
+            dimension: 3,
+            found: 5
+        }
+    );
+}
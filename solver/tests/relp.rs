@@ -0,0 +1,56 @@
+// Relp picks the cities the construction scored worst, which has to be the same cities however
+// the scores tie and whatever order the insertion log lists them in
+
+mod common;
+
+use common::{assert_permutation, instances};
+use tsp_rust::relp::{InsertPointResult, find_lowest_lda_points};
+use tsp_rust::{Solver, SolverConfig};
+
+fn log(scores: &[(f32, usize)]) -> Vec<InsertPointResult> {
+    scores
+        .iter()
+        .map(|&(lda, city)| InsertPointResult {
+            lda,
+            best_a: 0,
+            best_c: city,
+        })
+        .collect()
+}
+
+#[test]
+fn equal_scores_go_to_the_lowest_cities() {
+    let scores = [(2.0, 7), (1.0, 9), (1.0, 4), (3.0, 1), (1.0, 6), (1.0, 2)];
+    assert_eq!(find_lowest_lda_points(&log(&scores), 3), [2, 4, 6]);
+
+    let mut reversed = scores;
+    reversed.reverse();
+    assert_eq!(find_lowest_lda_points(&log(&reversed), 3), [2, 4, 6]);
+}
+
+#[test]
+fn a_nan_score_does_not_panic() {
+    let scores = [(1.0, 1), (f32::NAN, 2), (0.5, 3), (2.0, 4)];
+    assert_eq!(find_lowest_lda_points(&log(&scores), 2), [3, 1]);
+}
+
+// Relp runs after 3-opt unless asked to go first, and either way every city stays in the tour
+#[test]
+fn relp_before_or_after_the_local_searches() {
+    for instance in instances() {
+        for (relp_first, three_opt) in [(false, false), (true, false), (false, true), (true, true)]
+        {
+            let config = SolverConfig {
+                relp_first,
+                three_opt,
+                ..SolverConfig::default()
+            };
+            let solution = Solver::new(config).solve_instance(&instance);
+            let what = format!(
+                "relp first {}, 3-opt {} on {}",
+                relp_first, three_opt, instance.name
+            );
+            assert_permutation(&solution.tour, instance.points.len(), &what);
+        }
+    }
+}
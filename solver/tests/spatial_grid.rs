@@ -0,0 +1,44 @@
+// The grid only ever answers for the cities it holds right now, however often they were
+// taken out and put back
+
+use tsp_rust::Point;
+use tsp_rust::precompute::SpatialGrid;
+
+// A 10 by 10 lattice of cities one apart
+fn lattice() -> Vec<Point> {
+    (0..100)
+        .map(|i| Point {
+            x: (i % 10) as f32,
+            y: (i / 10) as f32,
+        })
+        .collect()
+}
+
+#[test]
+fn removed_cities_come_back() {
+    let points = lattice();
+    let held: Vec<usize> = (0..100).step_by(3).collect();
+    let mut grid = SpatialGrid::new(&points, &held);
+    assert_eq!(grid.cities(), held);
+    assert!(!grid.contains_point(1));
+
+    for &city in &held {
+        grid.remove_point(city);
+    }
+    assert!(grid.is_empty());
+    assert_eq!(grid.nearest(points[33]), None);
+
+    // Only cities the grid was built with can come back
+    grid.restore_point(1);
+    grid.restore_point(33);
+    grid.restore_point(66);
+    assert_eq!(grid.len(), 2);
+    assert_eq!(grid.cities(), [33, 66]);
+    assert_eq!(grid.nearest(points[34]), Some(33));
+
+    grid.remove_point(33);
+    grid.restore_point(33);
+    grid.restore_point(33);
+    assert_eq!(grid.len(), 2);
+    assert_eq!(grid.k_nearest(points[0], 5), [33, 66]);
+}